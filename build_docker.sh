@@ -24,10 +24,11 @@ fi
 perl -i -pe 's/\["cdylib", "rlib"\]/\["cdylib"\]/' Cargo.toml
 
 docker start $NAME
-docker exec -it $NAME /bin/bash -c "rustup toolchain install 1.56.0; rustup default 1.56.0; rustup target add wasm32-unknown-unknown; cargo build --target wasm32-unknown-unknown --release"
+docker exec -it $NAME /bin/bash -c "rustup toolchain install 1.56.0; rustup default 1.56.0; rustup target add wasm32-unknown-unknown; cargo build --target wasm32-unknown-unknown --release; cargo build -p mock-receiver --target wasm32-unknown-unknown --release"
 
 perl -i -pe 's/\["cdylib"\]/\["cdylib", "rlib"\]/' Cargo.toml
 
 mkdir -p res
 cp $DIR/target/wasm32-unknown-unknown/release/price_oracle.wasm $DIR/res/price_oracle.wasm
+cp $DIR/target/wasm32-unknown-unknown/release/mock_receiver.wasm $DIR/res/mock_receiver.wasm
 
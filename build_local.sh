@@ -6,3 +6,6 @@ RUSTFLAGS='-C link-arg=-s' cargo build --target wasm32-unknown-unknown --release
 cp target/wasm32-unknown-unknown/release/price_oracle.wasm ./res/
 
 perl -i -pe 's/\["cdylib"\]/\["cdylib", "rlib"\]/' Cargo.toml
+
+RUSTFLAGS='-C link-arg=-s' cargo build -p mock-receiver --target wasm32-unknown-unknown --release
+cp target/wasm32-unknown-unknown/release/mock_receiver.wasm ./res/
@@ -0,0 +1,27 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use near_sdk::serde_json::{self, json};
+use price_oracle::Price;
+
+fuzz_target!(|data: &[u8]| {
+    let s = match std::str::from_utf8(data) {
+        Ok(s) => s,
+        Err(_) => return,
+    };
+    // Any accepted document has to serialize back to an equal price.
+    if let Ok(price) = serde_json::from_str::<Price>(s) {
+        let json = serde_json::to_string(&price).unwrap();
+        let parsed: Price = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.multiplier, price.multiplier);
+        assert_eq!(parsed.decimals, price.decimals);
+    }
+    // The multiplier string is accepted exactly when it's a valid `u128`.
+    let res = serde_json::from_value::<Price>(json!({
+        "multiplier": s,
+        "decimals": 0,
+    }));
+    match s.parse::<u128>() {
+        Ok(multiplier) => assert_eq!(res.unwrap().multiplier, multiplier),
+        Err(_) => assert!(res.is_err()),
+    }
+});
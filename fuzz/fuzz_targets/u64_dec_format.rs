@@ -0,0 +1,27 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use near_sdk::serde_json::{self, json};
+use price_oracle::PriceData;
+
+fuzz_target!(|data: &[u8]| {
+    let s = match std::str::from_utf8(data) {
+        Ok(s) => s,
+        Err(_) => return,
+    };
+    // Any accepted document has to serialize back to the same timestamp.
+    if let Ok(price_data) = serde_json::from_str::<PriceData>(s) {
+        let json = serde_json::to_string(&price_data).unwrap();
+        let parsed: PriceData = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.timestamp, price_data.timestamp);
+    }
+    // The timestamp string is accepted exactly when it's a valid `u64`.
+    let res = serde_json::from_value::<PriceData>(json!({
+        "timestamp": s,
+        "recency_duration_sec": 0,
+        "prices": [],
+    }));
+    match s.parse::<u64>() {
+        Ok(timestamp) => assert_eq!(res.unwrap().timestamp, timestamp),
+        Err(_) => assert!(res.is_err()),
+    }
+});
@@ -0,0 +1,47 @@
+//! A receiver of `oracle_call` for end-to-end tests. It records every call and fails the call when
+//! `msg` is `"fail"`.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde_json::{self, json, Value};
+use near_sdk::{env, near_bindgen, AccountId};
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct ReceivedCall {
+    pub sender_id: AccountId,
+    /// The price data as JSON, so the receiver doesn't depend on the oracle types.
+    pub data: String,
+    pub msg: String,
+}
+
+#[near_bindgen]
+#[derive(BorshSerialize, BorshDeserialize, Default)]
+pub struct Contract {
+    pub calls: Vec<ReceivedCall>,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn oracle_on_call(&mut self, sender_id: AccountId, data: Value, msg: String) {
+        assert_ne!(msg, "fail", "The receiver failed on request");
+        self.calls.push(ReceivedCall {
+            sender_id,
+            data: data.to_string(),
+            msg,
+        });
+        env::log_str(&format!("Received oracle call #{}", self.calls.len()));
+    }
+
+    /// Returns the received calls in order as `{"sender_id", "data", "msg"}` objects.
+    pub fn get_calls(&self) -> Vec<Value> {
+        self.calls
+            .iter()
+            .map(|call| {
+                json!({
+                    "sender_id": call.sender_id,
+                    "data": serde_json::from_str::<Value>(&call.data).unwrap(),
+                    "msg": call.msg,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,207 @@
+use crate::*;
+
+/// How recent reports of an asset are combined into its price.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub enum Aggregation {
+    /// The plain (upper) median.
+    Median,
+    /// The median weighted by the oracle weights. The minimum number of recent reports is the
+    /// minimum total weight of recent reports.
+    WeightedMedian,
+    /// The mean after dropping `trim_bps` of the reports from both the top and the bottom.
+    TrimmedMean { trim_bps: u32 },
+    /// The mean of the middle half of the reports.
+    InterquartileMean,
+    /// The median of the bucket of `bucket_size` with the most reports. Suits assets pegged to a
+    /// value, where a few off-peg reports shouldn't move the price.
+    ModeOfBuckets { bucket_size: Price },
+}
+
+impl Aggregation {
+    pub fn assert_valid(&self) {
+        match self {
+            Aggregation::TrimmedMean { trim_bps } => {
+                assert!(*trim_bps < MAX_BPS / 2, "Can't trim all reports");
+            }
+            Aggregation::ModeOfBuckets { bucket_size } => {
+                bucket_size.assert_valid();
+                assert!(
+                    bucket_size.multiplier > 0,
+                    "The bucket size should be positive"
+                );
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns `None` if there are fewer than `min_num_recent_reports` reports, or if the price
+    /// can't be computed without an overflow.
+    pub fn aggregate(
+        &self,
+        reports: Vec<&CachedReport>,
+        min_num_recent_reports: usize,
+    ) -> Option<Price> {
+        let sorted = || sorted_prices(&reports, min_num_recent_reports);
+        match self {
+            Aggregation::Median => sorted().map(|prices| prices[prices.len() / 2]),
+            Aggregation::WeightedMedian => {
+                weighted_median_price(reports, min_num_recent_reports as u64)
+            }
+            Aggregation::TrimmedMean { trim_bps } => trimmed_mean_price(&sorted()?, *trim_bps),
+            Aggregation::InterquartileMean => trimmed_mean_price(&sorted()?, MAX_BPS / 4),
+            Aggregation::ModeOfBuckets { bucket_size } => {
+                mode_of_buckets_price(&sorted()?, bucket_size)
+            }
+        }
+    }
+}
+
+/// Returns the sorted report prices, or `None` if there are fewer than `min_num_recent_reports`.
+fn sorted_prices(reports: &[&CachedReport], min_num_recent_reports: usize) -> Option<Vec<Price>> {
+    if reports.is_empty() || reports.len() < min_num_recent_reports {
+        return None;
+    }
+    let mut prices: Vec<Price> = reports.iter().map(|rp| rp.price).collect();
+    prices.sort_unstable();
+    Some(prices)
+}
+
+/// The price at which the cumulative weight exceeds half of the total weight. With equal weights
+/// it's the same as the plain (upper) median.
+fn weighted_median_price(mut reports: Vec<&CachedReport>, min_total_weight: u64) -> Option<Price> {
+    let total_weight: u64 = reports.iter().map(|rp| rp.weight as u64).sum();
+    if total_weight == 0 || total_weight < min_total_weight {
+        return None;
+    }
+    reports.sort_unstable_by_key(|rp| rp.price);
+    let mut cumulative_weight = 0;
+    reports
+        .into_iter()
+        .find(|rp| {
+            cumulative_weight += rp.weight as u64;
+            cumulative_weight * 2 > total_weight
+        })
+        .map(|rp| rp.price)
+}
+
+/// The mean of sorted prices without `trim_bps` of them (rounded down) on each side.
+fn trimmed_mean_price(prices: &[Price], trim_bps: u32) -> Option<Price> {
+    let num_trimmed = prices.len() * trim_bps as usize / MAX_BPS as usize;
+    Price::checked_mean(&prices[num_trimmed..prices.len() - num_trimmed])
+}
+
+/// Groups sorted prices into buckets of `bucket_size` and returns the median of the largest one.
+/// A tie is resolved in favor of the bucket with the median of all prices, otherwise the lowest.
+fn mode_of_buckets_price(prices: &[Price], bucket_size: &Price) -> Option<Price> {
+    let decimals = prices
+        .iter()
+        .map(|price| price.decimals)
+        .chain(std::iter::once(bucket_size.decimals))
+        .max()
+        .unwrap();
+    let bucket_size = bucket_size.rescale(decimals, 0)?.multiplier;
+    let bucket_ids = prices
+        .iter()
+        .map(|price| Some(price.rescale(decimals, 0)?.multiplier / bucket_size))
+        .collect::<Option<Vec<u128>>>()?;
+    let median_bucket_id = bucket_ids[bucket_ids.len() / 2];
+
+    let mut best: Option<(usize, usize)> = None;
+    let mut start = 0;
+    while start < bucket_ids.len() {
+        let end = start
+            + bucket_ids[start..]
+                .iter()
+                .take_while(|&&bucket_id| bucket_id == bucket_ids[start])
+                .count();
+        let is_better = match best {
+            None => true,
+            Some((best_start, best_end)) => {
+                end - start > best_end - best_start
+                    || (end - start == best_end - best_start
+                        && bucket_ids[start] == median_bucket_id)
+            }
+        };
+        if is_better {
+            best = Some((start, end));
+        }
+        start = end;
+    }
+    best.map(|(start, end)| prices[(start + end) / 2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reports(multipliers: &[u128]) -> Vec<CachedReport> {
+        multipliers
+            .iter()
+            .map(|&multiplier| CachedReport {
+                oracle_id: AccountId::new_unchecked("oracle.near".to_string()),
+                timestamp: 0,
+                price: Price {
+                    multiplier,
+                    decimals: 4,
+                },
+                weight: 1,
+            })
+            .collect()
+    }
+
+    fn aggregate(aggregation: Aggregation, multipliers: &[u128]) -> Option<u128> {
+        let reports = reports(multipliers);
+        aggregation
+            .aggregate(reports.iter().collect(), 1)
+            .map(|price| price.rescale(4, MAX_BPS).unwrap().multiplier)
+    }
+
+    #[test]
+    fn test_trimmed_mean() {
+        let multipliers = [1, 100, 102, 104, 106, 108, 110, 112, 114, 10000];
+        assert_eq!(
+            aggregate(Aggregation::TrimmedMean { trim_bps: 1000 }, &multipliers),
+            Some(107)
+        );
+        assert_eq!(
+            aggregate(Aggregation::TrimmedMean { trim_bps: 0 }, &multipliers),
+            Some(1085)
+        );
+        assert_eq!(
+            aggregate(Aggregation::InterquartileMean, &multipliers),
+            Some(107)
+        );
+        assert_eq!(aggregate(Aggregation::InterquartileMean, &[5]), Some(5));
+    }
+
+    #[test]
+    fn test_mode_of_buckets() {
+        let bucket_size = Price {
+            multiplier: 10,
+            decimals: 4,
+        };
+        let aggregation = Aggregation::ModeOfBuckets { bucket_size };
+        // Most reports are at the peg even though the median is off it.
+        assert_eq!(
+            aggregate(
+                aggregation.clone(),
+                &[9990, 10001, 10003, 10100, 10200, 10300, 10400]
+            ),
+            Some(10003)
+        );
+        // Ties go to the bucket of the median.
+        assert_eq!(
+            aggregate(
+                aggregation.clone(),
+                &[9000, 9001, 10000, 10001, 11000, 11001]
+            ),
+            Some(10001)
+        );
+        assert_eq!(
+            aggregate(aggregation, &[9000, 9001, 10000, 11000, 11001]),
+            Some(9001)
+        );
+    }
+}
@@ -1,12 +1,91 @@
 use crate::*;
+use near_sdk::json_types::U64;
 
 pub type AssetId = String;
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct Asset {
-    pub reports: Vec<Report>,
+    /// Compact copies of the oracle reports that are used to compute the median price.
+    /// The full reports are stored separately in `Contract::reports`.
+    pub report_cache: Vec<CachedReport>,
     pub emas: Vec<AssetEma>,
+    /// The fraction of the period since the first sample, before which EMA prices are not
+    /// returned. `None` returns them from the first sample.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ema_warm_up_bps: Option<u32>,
+    /// Realized volatilities of the median over different windows.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub volatilities: Vec<AssetVolatility>,
+    /// The canonical decimals of the asset prices. `None` keeps reported prices as is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_decimals: Option<PriceDecimals>,
+    /// Sanity bounds for reported prices. `None` accepts any valid price.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_bounds: Option<PriceBounds>,
+    /// Oracles allowed to report the asset. `None` allows all oracles.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oracle_whitelist: Option<Vec<AccountId>>,
+    /// How recent reports are combined into the asset price. `None` uses the median.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregation: Option<Aggregation>,
+    /// Requires oracles to commit prices before revealing them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit_reveal: Option<CommitReveal>,
+    /// Marks the asset as a stablecoin, see `get_peg_status`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peg: Option<Peg>,
+    /// When the current depeg started, updated on reports.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depeg_started_at: Option<U64>,
+    /// The median price at the last report or confirmation. EMAs are only recomputed when it
+    /// changes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_median: Option<Price>,
+    /// The last time `last_median` was computed.
+    #[serde(default, with = "u64_dec_format")]
+    pub last_median_timestamp: Timestamp,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceDecimals {
+    /// Reported prices are rescaled to these decimals and views return prices with them.
+    pub decimals: u8,
+    /// The maximum precision that can be truncated from a reported price, in basis points of the
+    /// price. Reports that lose more are rejected.
+    pub tolerance_bps: u32,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CachedReport {
+    pub oracle_id: AccountId,
+    #[serde(with = "u64_dec_format")]
+    pub timestamp: Timestamp,
+    pub price: Price,
+    /// The oracle weight at the time of the report.
+    pub weight: u32,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceBounds {
+    pub min_price: Option<Price>,
+    pub max_price: Option<Price>,
+    /// The maximum change from the previous recent report of the same oracle, in basis points.
+    pub max_change_bps: Option<u32>,
+}
+
+impl CachedReport {
+    pub fn new(report: &Report, weight: u32) -> Self {
+        Self {
+            oracle_id: report.oracle_id.clone(),
+            timestamp: report.timestamp,
+            price: report.price,
+            weight,
+        }
+    }
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
@@ -25,6 +104,36 @@ pub struct AssetPrice {
     pub price: Price,
 }
 
+/// The outcome of a single asset in `report_prices`, `confirm_prices`, `commit_prices` and
+/// `reveal_prices`.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub enum ReportStatus {
+    Accepted,
+    UnknownAsset,
+    /// The price has invalid decimals, can't be rescaled to the asset decimals or is out of the
+    /// asset price bounds.
+    OutOfBounds,
+    /// The oracle is not in the asset whitelist.
+    NotWhitelisted,
+    /// The asset was already reported earlier in the same call, directly or through an alias.
+    Duplicate,
+    /// `confirm_prices` only: the oracle has no recent report for the asset.
+    MissingReport,
+    /// The asset uses commit-reveal, so prices have to be committed and revealed.
+    CommitRevealRequired,
+    /// The asset doesn't use commit-reveal.
+    CommitRevealDisabled,
+    /// The commitment is outside of the commit window or the reveal is outside of the reveal
+    /// window.
+    WrongPhase,
+    /// The oracle has no commitment for the asset in the current round.
+    NoCommitment,
+    /// The revealed price and salt don't match the commitment.
+    InvalidReveal,
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct AssetOptionalPrice {
@@ -32,9 +141,12 @@ pub struct AssetOptionalPrice {
     pub price: Option<Price>,
 }
 
+/// Only used to read and write storage, so the size difference between variants is irrelevant.
+#[allow(clippy::large_enum_variant)]
 #[derive(BorshSerialize, BorshDeserialize)]
 pub enum VAsset {
     V0(AssetV0),
+    V1(AssetV1),
     Current(Asset),
 }
 
@@ -42,6 +154,7 @@ impl From<VAsset> for Asset {
     fn from(v: VAsset) -> Self {
         match v {
             VAsset::V0(c) => c.into(),
+            VAsset::V1(c) => c.into(),
             VAsset::Current(c) => c,
         }
     }
@@ -56,46 +169,638 @@ impl From<Asset> for VAsset {
 impl Asset {
     pub fn new() -> Self {
         Self {
-            reports: Vec::new(),
+            report_cache: Vec::new(),
             emas: Vec::new(),
+            ema_warm_up_bps: None,
+            volatilities: Vec::new(),
+            price_decimals: None,
+            price_bounds: None,
+            oracle_whitelist: None,
+            aggregation: None,
+            commit_reveal: None,
+            peg: None,
+            depeg_started_at: None,
+            last_median: None,
+            last_median_timestamp: 0,
+        }
+    }
+
+    /// Recomputes the median and updates EMAs if it changed. EMAs catch up with their previous
+    /// inputs first. Median EMAs are updated before derived EMAs, which use their new prices.
+    /// `quote_median` returns the recent median of the quote asset of a pair EMA.
+    pub fn update_median(
+        &mut self,
+        timestamp_cut: Timestamp,
+        min_num_recent_reports: usize,
+        timestamp: Timestamp,
+        quote_median: &dyn Fn(&AssetId) -> Option<Price>,
+    ) {
+        let median = self.median_price(timestamp_cut, min_num_recent_reports);
+        let changed = median != self.last_median;
+        if changed {
+            for i in 0..self.emas.len() {
+                self.emas[i] = self.caught_up_ema(&self.emas[i]);
+            }
+            for volatility in self.volatilities.iter_mut() {
+                volatility.record(self.last_median, median, timestamp);
+            }
+            self.last_median = median;
+        }
+        self.last_median_timestamp = timestamp;
+        for derived in [false, true] {
+            for i in 0..self.emas.len() {
+                // EMAs added since the last change start with the current input.
+                if self.emas[i].source.is_some() != derived
+                    || (!changed && self.emas[i].price.is_some())
+                {
+                    continue;
+                }
+                if let Some(input) = self.ema_input(&self.emas[i].source, quote_median) {
+                    self.emas[i].sample(input, timestamp);
+                } else {
+                    self.emas[i].last_input = None;
+                }
+            }
+        }
+        self.update_peg(timestamp);
+    }
+
+    /// Returns the current value of the EMA source.
+    fn ema_input(
+        &self,
+        source: &Option<EmaSource>,
+        quote_median: &dyn Fn(&AssetId) -> Option<Price>,
+    ) -> Option<Price> {
+        let median = self.last_median?;
+        match source {
+            None => Some(median),
+            Some(EmaSource::Ema { period_sec }) => self.median_ema(*period_sec)?.price,
+            Some(EmaSource::Spread { period_sec }) => {
+                median.checked_div(&self.median_ema(*period_sec)?.price?)
+            }
+            Some(EmaSource::Pair { quote_asset_id }) => {
+                median.checked_div(&quote_median(quote_asset_id)?)
+            }
         }
     }
 
-    pub fn add_report(&mut self, report: Report) {
-        self.reports.push(report);
+    /// Returns the EMA of the median with the given period.
+    pub fn median_ema(&self, period_sec: DurationSec) -> Option<&AssetEma> {
+        self.emas
+            .iter()
+            .find(|ema| ema.source.is_none() && ema.period_sec == period_sec)
+    }
+
+    /// Returns the EMA recomputed with its last input at the time the median was last computed.
+    pub fn caught_up_ema(&self, ema: &AssetEma) -> AssetEma {
+        ema.caught_up(self.last_median_timestamp)
+    }
+
+    /// Returns the caught up EMA if it's warmed up, see `ema_warm_up_bps`.
+    pub fn warmed_up_ema(&self, ema: &AssetEma) -> Option<AssetEma> {
+        Some(self.caught_up_ema(ema))
+            .filter(|ema| ema.is_warmed_up(self.ema_warm_up_bps.unwrap_or(0)))
+    }
+
+    /// Returns the EMA price in the canonical decimals if it was computed after `timestamp_cut`
+    /// and is warmed up. Spread and pair EMAs are returned as is, since they are ratios rather
+    /// than prices of the asset.
+    pub fn ema_price(&self, key: &str, timestamp_cut: Timestamp) -> Option<Price> {
+        let ema = self
+            .emas
+            .iter()
+            .find(|ema| ema.key() == key)
+            .and_then(|ema| self.warmed_up_ema(ema))
+            .filter(|ema| ema.timestamp >= timestamp_cut)?;
+        match &ema.source {
+            None | Some(EmaSource::Ema { .. }) => {
+                ema.price.map(|price| self.to_canonical_price(price))
+            }
+            Some(EmaSource::Spread { .. }) | Some(EmaSource::Pair { .. }) => ema.price,
+        }
+    }
+
+    pub fn is_oracle_allowed(&self, oracle_id: &AccountId) -> bool {
+        self.oracle_whitelist
+            .as_ref()
+            .map(|oracle_ids| oracle_ids.contains(oracle_id))
+            .unwrap_or(true)
+    }
+
+    /// Returns the reason if the reported price is out of the asset bounds. The change is checked
+    /// against the previous price of the same oracle, if it's recent.
+    pub fn check_price_bounds(
+        &self,
+        price: &Price,
+        previous_price: Option<&Price>,
+    ) -> Result<(), String> {
+        let bounds = match &self.price_bounds {
+            Some(bounds) => bounds,
+            None => return Ok(()),
+        };
+        if let Some(min_price) = &bounds.min_price {
+            if price < min_price {
+                return Err("below the min price".to_string());
+            }
+        }
+        if let Some(max_price) = &bounds.max_price {
+            if price > max_price {
+                return Err("above the max price".to_string());
+            }
+        }
+        if let (Some(max_change_bps), Some(previous_price)) =
+            (bounds.max_change_bps, previous_price)
+        {
+            if previous_price.multiplier > 0 {
+                // The bound doesn't need the exact value, so the ratio is computed in f64.
+                let ratio = price.multiplier as f64 / previous_price.multiplier as f64
+                    * 10f64.powi(previous_price.decimals as i32 - price.decimals as i32);
+                let change_bps = (ratio - 1.0).abs() * MAX_BPS as f64;
+                if change_bps > max_change_bps as f64 {
+                    return Err(format!(
+                        "changed by {} bps from the previous report",
+                        change_bps.round()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rescales a reported price to the canonical decimals of the asset.
+    /// Returns `None` if the price loses more precision than the asset tolerates.
+    pub fn normalize_reported_price(&self, price: Price) -> Option<Price> {
+        match &self.price_decimals {
+            Some(pd) => price.rescale(pd.decimals, pd.tolerance_bps),
+            None => Some(price),
+        }
+    }
+
+    /// Rescales a computed price, e.g. the median or an EMA, to the canonical decimals of the
+    /// asset. The extra precision is truncated. The price is returned as is if it doesn't fit into
+    /// the canonical decimals.
+    pub fn to_canonical_price(&self, price: Price) -> Price {
+        match &self.price_decimals {
+            Some(pd) => price.rescale(pd.decimals, MAX_BPS).unwrap_or(price),
+            None => price,
+        }
     }
 
-    pub fn remove_report(&mut self, oracle_id: &AccountId) -> bool {
-        let initial_len = self.reports.len();
-        self.reports.retain(|rp| &rp.oracle_id != oracle_id);
-        self.reports.len() != initial_len
+    pub fn add_report(&mut self, report: &Report, weight: u32) {
+        self.report_cache.push(CachedReport::new(report, weight));
     }
 
+    /// Removes the cached entry of the oracle of the given report.
+    pub fn remove_report(&mut self, report: &Report) -> bool {
+        if let Some(index) = self
+            .report_cache
+            .iter()
+            .position(|rp| rp.oracle_id == report.oracle_id)
+        {
+            self.report_cache.swap_remove(index);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the price aggregated from recent reports, see `Aggregation`.
     pub fn median_price(
         &self,
         timestamp_cut: Timestamp,
         min_num_recent_reports: usize,
     ) -> Option<Price> {
-        let mut recent_reports: Vec<_> = self
-            .reports
+        let recent_reports: Vec<_> = self
+            .report_cache
             .iter()
             .filter(|rp| rp.timestamp >= timestamp_cut)
             .collect();
-        if recent_reports.len() < min_num_recent_reports {
-            return None;
-        }
-        let index = recent_reports.len() / 2;
-        recent_reports.select_nth_unstable_by(index, |a, b| a.price.cmp(&b.price));
-        recent_reports.get(index).map(|tp| tp.price)
+        self.aggregation
+            .as_ref()
+            .unwrap_or(&Aggregation::Median)
+            .aggregate(recent_reports, min_num_recent_reports)
     }
 }
 
 impl Contract {
+    /// Returns the asset ID the given alias points to, or the given asset ID if it's not an alias.
+    pub fn internal_resolve_asset_id(&self, asset_id: &AssetId) -> AssetId {
+        self.asset_aliases
+            .get(asset_id)
+            .unwrap_or_else(|| asset_id.clone())
+    }
+
+    /// Resolves the quote asset ID of a pair EMA source, since it may be given by an alias.
+    pub fn internal_resolve_ema_source(&self, source: Option<EmaSource>) -> Option<EmaSource> {
+        match source {
+            Some(EmaSource::Pair { quote_asset_id }) => Some(EmaSource::Pair {
+                quote_asset_id: self.internal_resolve_asset_id(&quote_asset_id),
+            }),
+            source => source,
+        }
+    }
+
     pub fn internal_get_asset(&self, asset_id: &AssetId) -> Option<Asset> {
         self.assets.get(asset_id).map(|v| v.into())
     }
 
+    /// Returns the IDs of assets with pair EMAs quoted in the given asset.
+    pub fn internal_pair_ema_asset_ids(&self, quote_asset_id: &AssetId) -> Vec<AssetId> {
+        self.assets
+            .iter()
+            .filter_map(|(asset_id, v)| {
+                let asset: Asset = v.into();
+                let is_quoted = asset.emas.iter().any(|ema| match &ema.source {
+                    Some(EmaSource::Pair { quote_asset_id: id }) => id == quote_asset_id,
+                    _ => false,
+                });
+                if is_quoted {
+                    Some(asset_id)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
     pub fn internal_set_asset(&mut self, asset_id: &AssetId, asset: Asset) {
+        self.assert_migrated();
         self.assets.insert(asset_id, &asset.into());
     }
+
+    /// Returns the report of the oracle. During the state migration, assets that are not migrated
+    /// yet still keep their reports.
+    pub fn internal_get_report(&self, asset_id: &AssetId, oracle_id: &AccountId) -> Option<Report> {
+        if self.migration_progress.is_some() {
+            let legacy_reports = match self.assets.get(asset_id) {
+                Some(VAsset::V0(a)) => Some(a.reports),
+                Some(VAsset::V1(a)) => Some(a.reports),
+                _ => None,
+            };
+            if let Some(reports) = legacy_reports {
+                return reports
+                    .into_iter()
+                    .find(|report| &report.oracle_id == oracle_id);
+            }
+        }
+        self.reports.get(&(asset_id.clone(), oracle_id.clone()))
+    }
+
+    /// Stores the report and replaces the previous report of the same oracle in the asset cache.
+    /// The cache entry keeps the given oracle weight.
+    pub fn internal_set_report(
+        &mut self,
+        asset_id: &AssetId,
+        asset: &mut Asset,
+        report: Report,
+        weight: u32,
+    ) {
+        self.assert_migrated();
+        if let Some(previous_report) = self
+            .reports
+            .insert(&(asset_id.clone(), report.oracle_id.clone()), &report)
+        {
+            asset.remove_report(&previous_report);
+        }
+        asset.add_report(&report, weight);
+    }
+
+    pub fn internal_remove_report(
+        &mut self,
+        asset_id: &AssetId,
+        asset: &mut Asset,
+        oracle_id: &AccountId,
+    ) -> bool {
+        self.assert_migrated();
+        if let Some(report) = self.reports.remove(&(asset_id.clone(), oracle_id.clone())) {
+            asset.remove_report(&report)
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use proptest::prelude::*;
+
+    fn asset(reports: &[(u32, u128)]) -> Asset {
+        let mut asset = Asset::new();
+        for (i, &(sec, multiplier)) in reports.iter().enumerate() {
+            asset.add_report(
+                &Report {
+                    oracle_id: AccountId::new_unchecked(format!("oracle_{}.near", i)),
+                    timestamp: to_nano(sec),
+                    price: Price {
+                        multiplier,
+                        decimals: 28,
+                    },
+                },
+                1,
+            );
+        }
+        asset
+    }
+
+    proptest! {
+        #[test]
+        fn prop_median_price(
+            reports in prop::collection::vec((0..100u32, 0..1000u128), 0..20),
+            cut_sec in 0..100u32,
+            min_num_recent_reports in 0..5usize,
+        ) {
+            let asset = asset(&reports);
+            let recent: Vec<Price> = asset
+                .report_cache
+                .iter()
+                .filter(|rp| rp.timestamp >= to_nano(cut_sec))
+                .map(|rp| rp.price)
+                .collect();
+            let median = asset.median_price(to_nano(cut_sec), min_num_recent_reports);
+            if recent.is_empty() || recent.len() < min_num_recent_reports {
+                prop_assert!(median.is_none());
+            } else {
+                let median = median.unwrap();
+                prop_assert!(recent.contains(&median));
+                // The upper median for an even number of reports.
+                let num_le = recent.iter().filter(|p| **p <= median).count();
+                let num_ge = recent.iter().filter(|p| **p >= median).count();
+                prop_assert!(num_le > recent.len() / 2);
+                prop_assert!(num_ge >= recent.len() - recent.len() / 2);
+            }
+        }
+
+        /// Reporters use different decimals for the same values.
+        #[test]
+        fn prop_median_price_mixed_decimals(
+            reports in prop::collection::vec((0..1_000_000u128, 0..20u8), 1..20),
+        ) {
+            let mut asset = Asset::new();
+            for (i, &(multiplier, extra_decimals)) in reports.iter().enumerate() {
+                asset.add_report(&Report {
+                    oracle_id: AccountId::new_unchecked(format!("oracle_{}.near", i)),
+                    timestamp: 0,
+                    price: Price {
+                        multiplier: multiplier * 10u128.pow(extra_decimals as u32),
+                        decimals: 20 + extra_decimals,
+                    },
+                }, 1);
+            }
+            let mut multipliers: Vec<u128> = reports.iter().map(|&(m, _)| m).collect();
+            multipliers.sort_unstable();
+            let expected = Price {
+                multiplier: multipliers[multipliers.len() / 2],
+                decimals: 20,
+            };
+            prop_assert_eq!(asset.median_price(0, 1), Some(expected));
+        }
+
+        #[test]
+        fn prop_median_price_ignores_order(
+            mut reports in prop::collection::vec((0..100u32, 0..1000u128), 1..20),
+        ) {
+            let median = asset(&reports).median_price(0, 1);
+            reports.reverse();
+            prop_assert_eq!(asset(&reports).median_price(0, 1), median);
+        }
+
+        #[test]
+        fn prop_weighted_median_equal_weights(
+            reports in prop::collection::vec((0..100u32, 0..1000u128), 0..20),
+            cut_sec in 0..100u32,
+            min_num_recent_reports in 0..5usize,
+        ) {
+            let mut asset = asset(&reports);
+            let median = asset.median_price(to_nano(cut_sec), min_num_recent_reports);
+            asset.aggregation = Some(Aggregation::WeightedMedian);
+            prop_assert_eq!(
+                asset.median_price(to_nano(cut_sec), min_num_recent_reports),
+                median
+            );
+        }
+
+        /// Skipping EMA updates while the median doesn't change gives the same EMA as
+        /// recomputing it on every report.
+        #[test]
+        fn prop_update_median_ema(
+            steps in prop::collection::vec((1..1000u32, 1..4u128), 1..30),
+        ) {
+            let oracle_id = AccountId::new_unchecked("oracle.near".to_string());
+            let mut asset = Asset::new();
+            asset.emas.push(AssetEma::new(600));
+            let mut expected = AssetEma::new(600);
+            let mut sec = 0;
+            let mut previous_report: Option<Report> = None;
+            for &(diff_sec, multiplier) in &steps {
+                sec += diff_sec;
+                let report = Report {
+                    oracle_id: oracle_id.clone(),
+                    timestamp: to_nano(sec),
+                    price: Price {
+                        multiplier: multiplier * 10u128.pow(12),
+                        decimals: 20,
+                    },
+                };
+                if let Some(previous_report) = previous_report.as_ref() {
+                    asset.remove_report(previous_report);
+                }
+                asset.add_report(&report, 1);
+                asset.update_median(0, 1, report.timestamp, &|_| None);
+                expected.recompute(report.price, report.timestamp);
+                previous_report = Some(report);
+            }
+            let actual = asset.caught_up_ema(&asset.emas[0]);
+            prop_assert_eq!(actual.timestamp, expected.timestamp);
+            let actual = actual.price.unwrap();
+            let expected = expected.price.unwrap();
+            let actual = actual.multiplier as f64 / 10f64.powi(actual.decimals as i32);
+            let expected = expected.multiplier as f64 / 10f64.powi(expected.decimals as i32);
+            prop_assert!((actual - expected).abs() <= expected * 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_weighted_median_price() {
+        let mut asset = Asset::new();
+        asset.aggregation = Some(Aggregation::WeightedMedian);
+        for (i, &(multiplier, weight)) in [(100u128, 1u32), (110, 3), (120, 1), (130, 0)]
+            .iter()
+            .enumerate()
+        {
+            asset.add_report(
+                &Report {
+                    oracle_id: AccountId::new_unchecked(format!("oracle_{}.near", i)),
+                    timestamp: 0,
+                    price: Price {
+                        multiplier,
+                        decimals: 28,
+                    },
+                },
+                weight,
+            );
+        }
+        let price = |multiplier| {
+            Some(Price {
+                multiplier,
+                decimals: 28,
+            })
+        };
+        assert_eq!(asset.median_price(0, 5), price(110));
+        // The quorum is the total weight, not the number of reports.
+        assert_eq!(asset.median_price(0, 6), None);
+        asset.aggregation = None;
+        assert_eq!(asset.median_price(0, 4), price(120));
+    }
+
+    #[test]
+    fn test_replace_report_with_same_price() {
+        let mut asset = Asset::new();
+        asset.aggregation = Some(Aggregation::WeightedMedian);
+        let report = |i: usize, multiplier: u128| Report {
+            oracle_id: AccountId::new_unchecked(format!("oracle_{}.near", i)),
+            timestamp: 0,
+            price: Price {
+                multiplier,
+                decimals: 28,
+            },
+        };
+        // Both oracles report the same price in the same block.
+        asset.add_report(&report(0, 100), 1);
+        asset.add_report(&report(1, 100), 3);
+        asset.add_report(&report(2, 120), 1);
+        assert!(asset.remove_report(&report(0, 100)));
+        asset.add_report(&report(0, 110), 1);
+
+        let weights: Vec<(String, u128, u32)> = asset
+            .report_cache
+            .iter()
+            .map(|rp| (rp.oracle_id.to_string(), rp.price.multiplier, rp.weight))
+            .collect();
+        assert_eq!(weights.len(), 3);
+        assert!(weights.contains(&("oracle_1.near".to_string(), 100, 3)));
+        assert!(weights.contains(&("oracle_0.near".to_string(), 110, 1)));
+        // The weight 3 of `oracle_1.near` is still at 100.
+        assert_eq!(
+            asset.median_price(0, 5),
+            Some(Price {
+                multiplier: 100,
+                decimals: 28,
+            })
+        );
+    }
+
+    #[test]
+    fn test_derived_emas() {
+        let oracle_id = AccountId::new_unchecked("oracle.near".to_string());
+        let quote_asset_id: AssetId = "quote.near".to_string();
+        let mut asset = Asset::new();
+        asset.emas = vec![
+            AssetEma::with_source(600, Some(EmaSource::Ema { period_sec: 600 })),
+            AssetEma::with_source(600, Some(EmaSource::Spread { period_sec: 600 })),
+            AssetEma::with_source(
+                600,
+                Some(EmaSource::Pair {
+                    quote_asset_id: quote_asset_id.clone(),
+                }),
+            ),
+            AssetEma::new(600),
+        ];
+        let quote_median = |id: &AssetId| {
+            assert_eq!(id, &quote_asset_id);
+            Some(Price {
+                multiplier: 50,
+                decimals: 20,
+            })
+        };
+        let report = |sec: u32, multiplier: u128, asset: &mut Asset| {
+            asset.report_cache.clear();
+            asset.add_report(
+                &Report {
+                    oracle_id: oracle_id.clone(),
+                    timestamp: to_nano(sec),
+                    price: Price {
+                        multiplier: multiplier * 10u128.pow(12),
+                        decimals: 32,
+                    },
+                },
+                1,
+            );
+            asset.update_median(0, 1, to_nano(sec), &quote_median);
+        };
+        let value = |asset: &Asset, key: &str| {
+            let price = asset.ema_price(key, 0).unwrap();
+            price.multiplier as f64 / 10f64.powi(price.decimals as i32)
+        };
+
+        report(0, 100, &mut asset);
+        assert_relative_eq!(value(&asset, "600"), 100e-20);
+        assert_relative_eq!(value(&asset, "600:ema:600"), 100e-20);
+        assert_relative_eq!(value(&asset, "600:spread:600"), 1.0);
+        assert_relative_eq!(value(&asset, "600:pair:quote.near"), 2.0);
+
+        report(600, 200, &mut asset);
+        let alpha = 1.0 - (-2.0f64).exp();
+        let ema = 100.0 + alpha * 100.0;
+        assert_relative_eq!(value(&asset, "600"), ema * 1e-20, max_relative = 1e-9);
+        assert_relative_eq!(
+            value(&asset, "600:ema:600"),
+            (100.0 + alpha * (ema - 100.0)) * 1e-20,
+            max_relative = 1e-9
+        );
+        assert_relative_eq!(
+            value(&asset, "600:spread:600"),
+            1.0 + alpha * (200.0 / ema - 1.0),
+            max_relative = 1e-9
+        );
+        assert_relative_eq!(value(&asset, "600:pair:quote.near"), 2.0 + alpha * 2.0);
+
+        // Derived EMAs catch up with their last inputs.
+        asset.last_median_timestamp = to_nano(1200);
+        assert_relative_eq!(
+            value(&asset, "600:pair:quote.near"),
+            4.0 - 2.0 * (-4.0f64).exp(),
+            max_relative = 1e-9
+        );
+
+        // EMAs of prices are returned in the canonical decimals, unlike ratios.
+        asset.price_decimals = Some(PriceDecimals {
+            decimals: 24,
+            tolerance_bps: 0,
+        });
+        let decimals = |key: &str| asset.ema_price(key, 0).unwrap().decimals;
+        let raw_decimals = |key: &str| {
+            let ema = asset.emas.iter().find(|ema| ema.key() == key).unwrap();
+            asset.caught_up_ema(ema).price.unwrap().decimals
+        };
+        assert_eq!(decimals("600"), 24);
+        assert_eq!(decimals("600:ema:600"), 24);
+        assert_eq!(decimals("600:spread:600"), raw_decimals("600:spread:600"));
+        assert_eq!(
+            decimals("600:pair:quote.near"),
+            raw_decimals("600:pair:quote.near")
+        );
+    }
+
+    #[test]
+    fn test_to_canonical_price() {
+        let p = |multiplier, decimals| Price {
+            multiplier,
+            decimals,
+        };
+        let mut asset = Asset::new();
+        asset.price_decimals = Some(PriceDecimals {
+            decimals: 4,
+            tolerance_bps: 0,
+        });
+        let canonical = |asset: &Asset, price: Price| {
+            let price = asset.to_canonical_price(price);
+            (price.multiplier, price.decimals)
+        };
+        assert_eq!(canonical(&asset, p(12345, 6)), (123, 4));
+        assert_eq!(canonical(&asset, p(123, 2)), (12300, 4));
+        // Doesn't fit into the canonical decimals.
+        assert_eq!(canonical(&asset, p(u128::MAX, 2)), (u128::MAX, 2));
+    }
 }
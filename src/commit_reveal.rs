@@ -0,0 +1,322 @@
+use crate::*;
+
+/// Commit-reveal rounds of an asset. Rounds are aligned to the block time: every round starts
+/// with the commit window followed by the reveal window.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CommitReveal {
+    pub commit_duration_sec: DurationSec,
+    pub reveal_duration_sec: DurationSec,
+}
+
+impl CommitReveal {
+    pub fn assert_valid(&self) {
+        assert!(
+            self.commit_duration_sec > 0 && self.reveal_duration_sec > 0,
+            "The commit and reveal durations should be positive"
+        );
+    }
+
+    fn round_duration(&self) -> Duration {
+        to_nano(self.commit_duration_sec) + to_nano(self.reveal_duration_sec)
+    }
+
+    pub fn round(&self, timestamp: Timestamp) -> u64 {
+        timestamp / self.round_duration()
+    }
+
+    pub fn is_commit_window(&self, timestamp: Timestamp) -> bool {
+        timestamp % self.round_duration() < to_nano(self.commit_duration_sec)
+    }
+}
+
+/// Commitments of an asset in its last commit-reveal round.
+#[derive(BorshSerialize, BorshDeserialize, Default)]
+pub struct AssetCommitments {
+    pub round: u64,
+    /// Commitment hashes of the round that were not revealed yet, keyed by oracle ID.
+    pub hashes: Vec<(AccountId, CryptoHash)>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AssetCommitment {
+    pub asset_id: AssetId,
+    /// See `get_commitment_hash`.
+    pub hash: Base58CryptoHash,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AssetReveal {
+    pub asset_id: AssetId,
+    pub price: Price,
+    pub salt: String,
+}
+
+/// sha256 of `{oracle_id}:{asset_id}:{multiplier}:{decimals}:{salt}`. The oracle ID prevents
+/// other oracles from reusing the commitment.
+fn commitment_hash(
+    oracle_id: &AccountId,
+    asset_id: &AssetId,
+    price: &Price,
+    salt: &str,
+) -> CryptoHash {
+    let mut hash = CryptoHash::default();
+    hash.copy_from_slice(&env::sha256(
+        format!(
+            "{}:{}:{}:{}:{}",
+            oracle_id, asset_id, price.multiplier, price.decimals, salt
+        )
+        .as_bytes(),
+    ));
+    hash
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Commits hashes of prices of commit-reveal assets during the commit window. A commitment
+    /// replaces the previous one of the oracle. Returns the status of every asset in the same
+    /// order.
+    pub fn commit_prices(&mut self, commitments: Vec<AssetCommitment>) -> Vec<ReportStatus> {
+        assert!(!commitments.is_empty());
+        self.assert_not_paused();
+        let oracle_id = env::predecessor_account_id();
+        let timestamp = env::block_timestamp();
+
+        let mut oracle = self.internal_get_oracle(&oracle_id).expect("Not an oracle");
+        oracle.last_report = timestamp;
+
+        let mut committed_asset_ids = Vec::with_capacity(commitments.len());
+        let statuses = commitments
+            .into_iter()
+            .map(|AssetCommitment { asset_id, hash }| {
+                let asset_id = self.internal_resolve_asset_id(&asset_id);
+                let status = self.internal_commit_price(
+                    &oracle_id,
+                    &mut oracle,
+                    &asset_id,
+                    hash.into(),
+                    timestamp,
+                    &committed_asset_ids,
+                );
+                if status != ReportStatus::Accepted {
+                    log!("Warning! Can't commit price for {}", asset_id);
+                }
+                committed_asset_ids.push(asset_id);
+                status
+            })
+            .collect();
+
+        self.internal_set_oracle(&oracle_id, oracle);
+        statuses
+    }
+
+    /// Reveals prices committed in the commit window of the current round. Revealed prices are
+    /// reported like in `report_prices`. Returns the status of every asset in the same order.
+    pub fn reveal_prices(&mut self, prices: Vec<AssetReveal>) -> Vec<ReportStatus> {
+        assert!(!prices.is_empty());
+        self.assert_not_paused();
+        let oracle_id = env::predecessor_account_id();
+        let timestamp = env::block_timestamp();
+
+        let mut oracle = self.internal_get_oracle(&oracle_id).expect("Not an oracle");
+        oracle.last_report = timestamp;
+
+        let mut revealed_asset_ids = Vec::with_capacity(prices.len());
+        let statuses = prices
+            .into_iter()
+            .map(|reveal| {
+                let asset_id = self.internal_resolve_asset_id(&reveal.asset_id);
+                let status = if revealed_asset_ids.contains(&asset_id) {
+                    ReportStatus::Duplicate
+                } else {
+                    match self.internal_reveal_commitment(
+                        &oracle_id,
+                        &mut oracle,
+                        &asset_id,
+                        &reveal,
+                        timestamp,
+                    ) {
+                        Ok(()) => self.internal_report_price(
+                            &asset_id,
+                            Report {
+                                oracle_id: oracle_id.clone(),
+                                timestamp,
+                                price: reveal.price,
+                            },
+                            oracle.weight,
+                            &revealed_asset_ids,
+                            true,
+                        ),
+                        Err(status) => status,
+                    }
+                };
+                match status {
+                    ReportStatus::OutOfBounds
+                    | ReportStatus::NotWhitelisted
+                    | ReportStatus::InvalidReveal => oracle.rejected_reports += 1,
+                    ReportStatus::Accepted => oracle.price_reports += 1,
+                    _ => {
+                        log!("Warning! Can't reveal price for {}", asset_id);
+                    }
+                }
+                revealed_asset_ids.push(asset_id);
+                status
+            })
+            .collect();
+
+        self.internal_set_oracle(&oracle_id, oracle);
+        statuses
+    }
+
+    /// Returns the hash an oracle has to commit for the price. Oracles should use a new random
+    /// salt for every commitment.
+    pub fn get_commitment_hash(
+        &self,
+        oracle_id: AccountId,
+        asset_id: AssetId,
+        price: Price,
+        salt: String,
+    ) -> Base58CryptoHash {
+        let asset_id = self.internal_resolve_asset_id(&asset_id);
+        commitment_hash(&oracle_id, &asset_id, &price, &salt).into()
+    }
+}
+
+impl Contract {
+    fn internal_commit_price(
+        &mut self,
+        oracle_id: &AccountId,
+        oracle: &mut Oracle,
+        asset_id: &AssetId,
+        hash: CryptoHash,
+        timestamp: Timestamp,
+        committed_asset_ids: &[AssetId],
+    ) -> ReportStatus {
+        let asset = if let Some(asset) = self.internal_get_asset(asset_id) {
+            asset
+        } else {
+            return ReportStatus::UnknownAsset;
+        };
+        if committed_asset_ids.contains(asset_id) {
+            return ReportStatus::Duplicate;
+        }
+        if !asset.is_oracle_allowed(oracle_id) {
+            return ReportStatus::NotWhitelisted;
+        }
+        let commit_reveal = if let Some(commit_reveal) = asset.commit_reveal {
+            commit_reveal
+        } else {
+            return ReportStatus::CommitRevealDisabled;
+        };
+        if !commit_reveal.is_commit_window(timestamp) {
+            return ReportStatus::WrongPhase;
+        }
+        let mut commitments = self.internal_get_commitments(
+            asset_id,
+            commit_reveal.round(timestamp),
+            oracle_id,
+            oracle,
+        );
+        commitments.hashes.retain(|(id, _)| id != oracle_id);
+        commitments.hashes.push((oracle_id.clone(), hash));
+        self.commitments.insert(asset_id, &commitments);
+        ReportStatus::Accepted
+    }
+
+    /// Returns the commitments of the asset in the given round. Commitments of earlier rounds were
+    /// never revealed, so they are dropped and counted in the stats of their oracles. The given
+    /// oracle is updated in place, since the caller saves it.
+    fn internal_get_commitments(
+        &mut self,
+        asset_id: &AssetId,
+        round: u64,
+        oracle_id: &AccountId,
+        oracle: &mut Oracle,
+    ) -> AssetCommitments {
+        let commitments = self.commitments.get(asset_id).unwrap_or_default();
+        if commitments.round == round {
+            return commitments;
+        }
+        for (unrevealed_oracle_id, _) in commitments.hashes {
+            if &unrevealed_oracle_id == oracle_id {
+                oracle.unrevealed_commitments += 1;
+            } else if let Some(mut unrevealed_oracle) =
+                self.internal_get_oracle(&unrevealed_oracle_id)
+            {
+                unrevealed_oracle.unrevealed_commitments += 1;
+                self.internal_set_oracle(&unrevealed_oracle_id, unrevealed_oracle);
+            }
+        }
+        AssetCommitments {
+            round,
+            hashes: vec![],
+        }
+    }
+
+    /// Removes the matching commitment of the current round. A mismatching commitment is kept.
+    fn internal_reveal_commitment(
+        &mut self,
+        oracle_id: &AccountId,
+        oracle: &mut Oracle,
+        asset_id: &AssetId,
+        reveal: &AssetReveal,
+        timestamp: Timestamp,
+    ) -> Result<(), ReportStatus> {
+        let commit_reveal = self
+            .internal_get_asset(asset_id)
+            .ok_or(ReportStatus::UnknownAsset)?
+            .commit_reveal
+            .ok_or(ReportStatus::CommitRevealDisabled)?;
+        if commit_reveal.is_commit_window(timestamp) {
+            return Err(ReportStatus::WrongPhase);
+        }
+        let mut commitments = self.internal_get_commitments(
+            asset_id,
+            commit_reveal.round(timestamp),
+            oracle_id,
+            oracle,
+        );
+        let res = match commitments
+            .hashes
+            .iter()
+            .position(|(id, _)| id == oracle_id)
+        {
+            None => Err(ReportStatus::NoCommitment),
+            Some(index)
+                if commitments.hashes[index].1
+                    != commitment_hash(oracle_id, asset_id, &reveal.price, &reveal.salt) =>
+            {
+                log!("Warning! Rejected price for {}: invalid reveal", asset_id);
+                Err(ReportStatus::InvalidReveal)
+            }
+            Some(index) => {
+                commitments.hashes.swap_remove(index);
+                Ok(())
+            }
+        };
+        self.commitments.insert(asset_id, &commitments);
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commit_reveal_windows() {
+        let commit_reveal = CommitReveal {
+            commit_duration_sec: 60,
+            reveal_duration_sec: 30,
+        };
+        assert!(commit_reveal.is_commit_window(0));
+        assert!(commit_reveal.is_commit_window(to_nano(59)));
+        assert!(!commit_reveal.is_commit_window(to_nano(60)));
+        assert!(!commit_reveal.is_commit_window(to_nano(89)));
+        assert!(commit_reveal.is_commit_window(to_nano(90)));
+        assert_eq!(commit_reveal.round(to_nano(89)), 0);
+        assert_eq!(commit_reveal.round(to_nano(90)), 1);
+    }
+}
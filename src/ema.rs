@@ -2,22 +2,107 @@ use crate::*;
 
 const MAX_F64_FOR_PRECISE_MULTIPLIER: f64 = 1e30;
 
+/// The value smoothed by a derived EMA. EMAs without a source smooth the asset median.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub enum EmaSource {
+    /// The median EMA of the asset with the given period, e.g. for a double-smoothed EMA.
+    Ema { period_sec: DurationSec },
+    /// The median divided by the median EMA with the given period. It's above 1 when the median
+    /// is above the EMA.
+    Spread { period_sec: DurationSec },
+    /// The median divided by the median of the quote asset. It's only sampled when the median of
+    /// this asset changes, so changes of the quote median alone are not reflected until then.
+    Pair { quote_asset_id: AssetId },
+}
+
+/// Whether the EMA key is of a spread or a pair EMA, which are ratios rather than asset prices.
+pub fn is_ratio_ema_key(key: &str) -> bool {
+    matches!(key.split(':').nth(1), Some("spread") | Some("pair"))
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct AssetEma {
     pub period_sec: DurationSec,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<EmaSource>,
     #[serde(with = "u64_dec_format")]
     pub timestamp: Timestamp,
     pub price: Option<Price>,
+    /// The source value at the last median change. It's held until the next change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_input: Option<Price>,
+    /// The time of the first sample.
+    #[serde(default, with = "u64_dec_format")]
+    pub started_at: Timestamp,
+    /// The number of samples since `started_at`.
+    #[serde(default)]
+    pub num_samples: u64,
 }
 
 impl AssetEma {
     pub fn new(period_sec: DurationSec) -> AssetEma {
+        Self::with_source(period_sec, None)
+    }
+
+    pub fn with_source(period_sec: DurationSec, source: Option<EmaSource>) -> AssetEma {
         Self {
             period_sec,
+            source,
             timestamp: 0,
             price: None,
+            last_input: None,
+            started_at: 0,
+            num_samples: 0,
+        }
+    }
+
+    /// Recomputes the EMA with a new source value that is held until the next sample.
+    pub fn sample(&mut self, input: Price, timestamp: Timestamp) {
+        if self.price.is_none() {
+            self.started_at = timestamp;
+        }
+        self.recompute(input, timestamp);
+        self.num_samples += 1;
+        self.last_input = Some(input);
+    }
+
+    /// Whether `warm_up_bps` of the period has passed from the first sample to the last update.
+    pub fn is_warmed_up(&self, warm_up_bps: u32) -> bool {
+        let warm_up_duration =
+            to_nano(self.period_sec) as u128 * warm_up_bps as u128 / MAX_BPS as u128;
+        self.price.is_some() && self.timestamp as u128 >= self.started_at as u128 + warm_up_duration
+    }
+
+    /// The EMA ID after `#` in price queries, e.g. `3600` for the median EMA,
+    /// `3600:ema:600`, `3600:spread:600` or `3600:pair:usdt.tether-token.near` for derived ones.
+    pub fn key(&self) -> String {
+        match &self.source {
+            None => self.period_sec.to_string(),
+            Some(EmaSource::Ema { period_sec }) => {
+                format!("{}:ema:{}", self.period_sec, period_sec)
+            }
+            Some(EmaSource::Spread { period_sec }) => {
+                format!("{}:spread:{}", self.period_sec, period_sec)
+            }
+            Some(EmaSource::Pair { quote_asset_id }) => {
+                format!("{}:pair:{}", self.period_sec, quote_asset_id)
+            }
+        }
+    }
+
+    /// Returns the EMA recomputed with the last input at the given time. Recomputing an EMA with
+    /// the same price several times is equivalent to recomputing it once at the last time.
+    pub fn caught_up(&self, timestamp: Timestamp) -> AssetEma {
+        let mut ema = self.clone();
+        if let Some(last_input) = self.last_input {
+            if timestamp > ema.timestamp {
+                ema.recompute(last_input, timestamp);
+            }
         }
+        ema
     }
 
     pub fn recompute(&mut self, median_price: Price, timestamp: Timestamp) {
@@ -29,10 +114,14 @@ impl AssetEma {
             let mut current_f64 = current.multiplier as f64;
             current_f64 *= 10f64.powi(median_price.decimals as i32 - current.decimals as i32);
             current_f64 += alpha * (median_price.multiplier as f64 - current_f64);
+            // Up to 4 decimals of precision are added, as long as the decimals stay valid, e.g. for
+            // an EMA of an EMA with high decimals.
+            let extra_decimals =
+                std::cmp::min(4, MAX_VALID_DECIMALS.saturating_sub(median_price.decimals));
             if current_f64 <= MAX_F64_FOR_PRECISE_MULTIPLIER {
                 *current = Price {
-                    multiplier: (current_f64 * 1e4).round() as u128,
-                    decimals: median_price.decimals + 4,
+                    multiplier: (current_f64 * 10f64.powi(extra_decimals as i32)).round() as u128,
+                    decimals: median_price.decimals + extra_decimals,
                 }
             } else {
                 *current = Price {
@@ -49,9 +138,10 @@ impl AssetEma {
 
 #[cfg(test)]
 mod tests {
-    use crate::{to_nano, AssetEma, Price};
+    use crate::{to_nano, AssetEma, Price, MAX_VALID_DECIMALS};
     use approx::assert_relative_eq;
     use near_sdk::Timestamp;
+    use proptest::prelude::*;
 
     fn ts(sec: u32) -> Timestamp {
         to_nano(1_600_000_000 + sec)
@@ -68,11 +158,7 @@ mod tests {
 
     #[test]
     pub fn test_ema_init() {
-        let mut ema = AssetEma {
-            period_sec: 60000,
-            timestamp: ts(0),
-            price: None,
-        };
+        let mut ema = AssetEma::new(60000);
         let timestamp = ts(10);
         let price = mp(100000);
         ema.recompute(price, timestamp);
@@ -94,11 +180,7 @@ mod tests {
         ];
         let step = 60;
         let period_sec = step * 10;
-        let mut ema = AssetEma {
-            period_sec,
-            timestamp: ts(0),
-            price: None,
-        };
+        let mut ema = AssetEma::new(period_sec);
         for (i, (multiplier, expected_ema)) in
             price_multipliers.into_iter().zip(expected_emas).enumerate()
         {
@@ -114,4 +196,84 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    pub fn test_ema_warm_up() {
+        let mut ema = AssetEma::new(600);
+        assert!(!ema.is_warmed_up(0));
+        ema.sample(mp(100000), ts(10));
+        ema.sample(mp(110000), ts(100));
+        assert_eq!(ema.started_at, ts(10));
+        assert_eq!(ema.num_samples, 2);
+        assert!(ema.is_warmed_up(0));
+        assert!(ema.is_warmed_up(1500));
+        assert!(!ema.is_warmed_up(5000));
+        // Catching up counts towards the warm-up, but not as a sample.
+        let ema = ema.caught_up(ts(310));
+        assert_eq!(ema.num_samples, 2);
+        assert!(ema.is_warmed_up(5000));
+        assert!(!ema.is_warmed_up(10000));
+    }
+
+    #[test]
+    pub fn test_chained_ema_decimals() {
+        let mut ema = AssetEma::new(600);
+        let mut chained = AssetEma::new(600);
+        let mut price = Price {
+            multiplier: 100000,
+            decimals: 70,
+        };
+        for i in 1..=3 {
+            price.multiplier += 10000;
+            ema.recompute(price, ts(60 * i));
+            chained.recompute(ema.price.unwrap(), ts(60 * i));
+        }
+        let ema_price = ema.price.unwrap();
+        let chained_price = chained.price.unwrap();
+        assert_eq!(ema_price.decimals, 74);
+        assert_eq!(chained_price.decimals, MAX_VALID_DECIMALS);
+        assert!(chained_price.is_valid());
+        assert_relative_eq!(value(&chained_price), value(&ema_price), max_relative = 0.1);
+    }
+
+    fn value(price: &Price) -> f64 {
+        price.multiplier as f64 / 10f64.powi(price.decimals as i32)
+    }
+
+    proptest! {
+        #[test]
+        fn prop_ema_bounds(
+            period_sec in 1..100000u32,
+            steps in prop::collection::vec((0..10000u32, 1..1_000_000_000_000u128), 1..30),
+        ) {
+            let mut ema = AssetEma::new(period_sec);
+            let mut sec = 0;
+            let mut min = f64::MAX;
+            let mut max = f64::MIN;
+            for (time_diff, multiplier) in steps {
+                sec += time_diff;
+                let price = mp(multiplier);
+                min = min.min(value(&price));
+                max = max.max(value(&price));
+                ema.recompute(price, ts(sec));
+                prop_assert_eq!(ema.timestamp, ts(sec));
+                // The EMA stays within the reported prices up to the rounding of the multiplier.
+                let ema_value = value(ema.price.as_ref().unwrap());
+                let tolerance = 1e-9 * max + 1.0 / 10f64.powi(BASE_DECIMALS as i32 + 4);
+                prop_assert!(ema_value >= min - tolerance, "{} < {}", ema_value, min);
+                prop_assert!(ema_value <= max + tolerance, "{} > {}", ema_value, max);
+            }
+        }
+
+        #[test]
+        fn prop_ema_no_time_diff(
+            first in 1..1_000_000_000_000u128,
+            second in 1..1_000_000_000_000u128,
+        ) {
+            let mut ema = AssetEma::new(600);
+            ema.recompute(mp(first), ts(10));
+            ema.recompute(mp(second), ts(10));
+            prop_assert_eq!(ema.price, Some(mp(first)));
+        }
+    }
 }
@@ -0,0 +1,157 @@
+use crate::*;
+use near_sdk::json_types::Base58CryptoHash;
+
+const EVENT_STANDARD: &str = "price-oracle";
+const EVENT_STANDARD_VERSION: &str = "1.0.0";
+
+pub mod emit {
+    use super::*;
+    use near_sdk::serde_json::json;
+
+    #[derive(Serialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct AssetAlias<'a> {
+        pub alias_id: &'a AssetId,
+        pub asset_id: &'a AssetId,
+    }
+
+    #[derive(Serialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct AssetRename<'a> {
+        pub old_asset_id: &'a AssetId,
+        pub new_asset_id: &'a AssetId,
+    }
+
+    #[derive(Serialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct ProposalAction<'a> {
+        pub proposal_id: ProposalId,
+        #[serde(flatten)]
+        pub proposal: &'a Proposal,
+    }
+
+    #[derive(Serialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct ProposalIdOnly {
+        pub proposal_id: ProposalId,
+    }
+
+    #[derive(Serialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct OwnerChange<'a> {
+        pub old_owner_id: &'a AccountId,
+        pub new_owner_id: &'a AccountId,
+    }
+
+    #[derive(Serialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct Account<'a> {
+        pub account_id: &'a AccountId,
+    }
+
+    #[derive(Serialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct AccountRole<'a> {
+        pub account_id: &'a AccountId,
+        pub role: Role,
+    }
+
+    #[derive(Serialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct CodeHash<'a> {
+        pub code_hash: &'a Base58CryptoHash,
+    }
+
+    fn log_event<T: Serialize>(event: &str, data: T) {
+        let event = json!({
+            "standard": EVENT_STANDARD,
+            "version": EVENT_STANDARD_VERSION,
+            "event": event,
+            "data": [data]
+        });
+
+        log!("EVENT_JSON:{}", event.to_string());
+    }
+
+    pub fn add_asset_alias(alias_id: &AssetId, asset_id: &AssetId) {
+        log_event("add_asset_alias", AssetAlias { alias_id, asset_id });
+    }
+
+    pub fn remove_asset_alias(alias_id: &AssetId, asset_id: &AssetId) {
+        log_event("remove_asset_alias", AssetAlias { alias_id, asset_id });
+    }
+
+    pub fn rename_asset(old_asset_id: &AssetId, new_asset_id: &AssetId) {
+        log_event(
+            "rename_asset",
+            AssetRename {
+                old_asset_id,
+                new_asset_id,
+            },
+        );
+    }
+
+    pub fn propose_action(proposal_id: ProposalId, proposal: &Proposal) {
+        log_event(
+            "propose_action",
+            ProposalAction {
+                proposal_id,
+                proposal,
+            },
+        );
+    }
+
+    pub fn execute_proposal(proposal_id: ProposalId) {
+        log_event("execute_proposal", ProposalIdOnly { proposal_id });
+    }
+
+    pub fn cancel_proposal(proposal_id: ProposalId) {
+        log_event("cancel_proposal", ProposalIdOnly { proposal_id });
+    }
+
+    pub fn pause() {
+        log_event("pause", json!({}));
+    }
+
+    pub fn propose_owner(pending_owner: &PendingOwner) {
+        log_event("propose_owner", pending_owner);
+    }
+
+    pub fn cancel_owner_proposal(account_id: &AccountId) {
+        log_event("cancel_owner_proposal", Account { account_id });
+    }
+
+    pub fn accept_ownership(old_owner_id: &AccountId, new_owner_id: &AccountId) {
+        log_event(
+            "accept_ownership",
+            OwnerChange {
+                old_owner_id,
+                new_owner_id,
+            },
+        );
+    }
+
+    pub fn grant_role(account_id: &AccountId, role: Role) {
+        log_event("grant_role", AccountRole { account_id, role });
+    }
+
+    pub fn revoke_role(account_id: &AccountId, role: Role) {
+        log_event("revoke_role", AccountRole { account_id, role });
+    }
+
+    pub fn stage_upgrade(code_hash: &Base58CryptoHash) {
+        log_event("stage_upgrade", CodeHash { code_hash });
+    }
+
+    pub fn cancel_staged_upgrade(code_hash: &Base58CryptoHash) {
+        log_event("cancel_staged_upgrade", CodeHash { code_hash });
+    }
+
+    pub fn deploy_upgrade(code_hash: &Base58CryptoHash) {
+        log_event("deploy_upgrade", CodeHash { code_hash });
+    }
+
+    pub fn rollback(code_hash: &Base58CryptoHash) {
+        log_event("rollback", CodeHash { code_hash });
+    }
+}
@@ -5,11 +5,153 @@ pub struct AssetV0 {
     pub reports: Vec<Report>,
 }
 
-impl From<AssetV0> for Asset {
+impl From<AssetV0> for AssetV1 {
     fn from(v: AssetV0) -> Self {
-        Asset {
+        AssetV1 {
             reports: v.reports,
             emas: vec![],
         }
     }
 }
+
+impl From<AssetV0> for Asset {
+    fn from(v: AssetV0) -> Self {
+        AssetV1::from(v).into()
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct AssetEmaV0 {
+    pub period_sec: DurationSec,
+    pub timestamp: Timestamp,
+    pub price: Option<Price>,
+}
+
+impl From<AssetEmaV0> for AssetEma {
+    fn from(v: AssetEmaV0) -> Self {
+        AssetEma {
+            period_sec: v.period_sec,
+            source: None,
+            timestamp: v.timestamp,
+            price: v.price,
+            last_input: None,
+            // Existing EMAs are considered warmed up.
+            started_at: 0,
+            num_samples: 0,
+        }
+    }
+}
+
+/// Only EMAs of the median can be rolled back.
+impl From<AssetEma> for AssetEmaV0 {
+    fn from(v: AssetEma) -> Self {
+        AssetEmaV0 {
+            period_sec: v.period_sec,
+            timestamp: v.timestamp,
+            price: v.price,
+        }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct AssetV1 {
+    pub reports: Vec<Report>,
+    pub emas: Vec<AssetEmaV0>,
+}
+
+/// The full reports are moved to `Contract::reports` by the state migration.
+impl From<AssetV1> for Asset {
+    fn from(v: AssetV1) -> Self {
+        Asset {
+            report_cache: v
+                .reports
+                .iter()
+                .map(|report| CachedReport::new(report, DEFAULT_ORACLE_WEIGHT))
+                .collect(),
+            emas: v.emas.into_iter().map(|ema| ema.into()).collect(),
+            ema_warm_up_bps: None,
+            volatilities: Vec::new(),
+            price_decimals: None,
+            price_bounds: None,
+            oracle_whitelist: None,
+            aggregation: None,
+            commit_reveal: None,
+            peg: None,
+            depeg_started_at: None,
+            last_median: None,
+            last_median_timestamp: 0,
+        }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct OracleV1 {
+    pub last_report: Timestamp,
+    pub price_reports: u64,
+    pub last_near_claim: Timestamp,
+}
+
+impl From<OracleV1> for Oracle {
+    fn from(v: OracleV1) -> Self {
+        Oracle {
+            last_report: v.last_report,
+            price_reports: v.price_reports,
+            last_near_claim: v.last_near_claim,
+            rejected_reports: 0,
+            unrevealed_commitments: 0,
+            weight: DEFAULT_ORACLE_WEIGHT,
+        }
+    }
+}
+
+/// Used by the rollback to 0.6.0.
+impl From<Oracle> for OracleV1 {
+    fn from(c: Oracle) -> Self {
+        OracleV1 {
+            last_report: c.last_report,
+            price_reports: c.price_reports,
+            last_near_claim: c.last_near_claim,
+        }
+    }
+}
+
+/// Also written back by the rollback to 0.6.0.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct ContractV0 {
+    pub oracles: UnorderedMap<AccountId, VOracle>,
+    pub assets: UnorderedMap<AssetId, VAsset>,
+    pub recency_duration_sec: DurationSec,
+    pub owner_id: AccountId,
+    pub near_claim_amount: Balance,
+}
+
+impl From<ContractV0> for Contract {
+    fn from(c: ContractV0) -> Self {
+        // The owner keeps access to all actions.
+        let mut roles = UnorderedMap::new(StorageKey::Roles);
+        roles.insert(&c.owner_id, &Role::ALL.to_vec());
+        // Oracles of 0.6.0 get the default weight.
+        let total_oracle_weight = c.oracles.len() * DEFAULT_ORACLE_WEIGHT as u64;
+        Contract {
+            oracles: c.oracles,
+            assets: c.assets,
+            recency_duration_sec: c.recency_duration_sec,
+            owner_id: c.owner_id,
+            near_claim_amount: c.near_claim_amount,
+            asset_aliases: UnorderedMap::new(StorageKey::AssetAliases),
+            reports: LookupMap::new(StorageKey::Reports),
+            proposals: UnorderedMap::new(StorageKey::Proposals),
+            next_proposal_id: 0,
+            timelock_duration_sec: DEFAULT_TIMELOCK_DURATION_SEC,
+            paused: false,
+            pending_owner: None,
+            roles,
+            staged_upgrade: None,
+            migration_progress: None,
+            code_hash: None,
+            previous_code: None,
+            total_oracle_weight,
+            commitments: LookupMap::new(StorageKey::Commitments),
+        }
+    }
+}
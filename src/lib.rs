@@ -1,24 +1,43 @@
+mod aggregation;
 mod asset;
+mod commit_reveal;
 mod ema;
+mod events;
 mod legacy;
+mod migration;
 mod oracle;
 mod owner;
+mod peg;
+mod roles;
+mod storage;
+mod timelock;
 mod upgrade;
 mod utils;
+mod volatility;
 
+pub use crate::aggregation::*;
 pub use crate::asset::*;
+pub use crate::commit_reveal::*;
 pub use crate::ema::*;
 use crate::legacy::*;
+pub use crate::migration::*;
 pub use crate::oracle::*;
+pub use crate::owner::*;
+pub use crate::peg::*;
+pub use crate::roles::*;
+pub use crate::storage::*;
+pub use crate::timelock::*;
+pub use crate::upgrade::*;
 pub use crate::utils::*;
+pub use crate::volatility::*;
 
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::UnorderedMap;
-use near_sdk::json_types::U128;
+use near_sdk::collections::{LookupMap, UnorderedMap};
+use near_sdk::json_types::{Base58CryptoHash, U128};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
     assert_one_yocto, env, ext_contract, log, near_bindgen, AccountId, Balance, BorshStorageKey,
-    Duration, Gas, PanicOnDefault, Promise, Timestamp, ONE_NEAR,
+    CryptoHash, Duration, Gas, PanicOnDefault, Promise, Timestamp, ONE_NEAR,
 };
 
 const NO_DEPOSIT: Balance = 0;
@@ -35,6 +54,13 @@ pub type DurationSec = u32;
 enum StorageKey {
     Oracles,
     Assets,
+    AssetAliases,
+    Reports,
+    Proposals,
+    Roles,
+    StagedCode,
+    SchemaVersion,
+    Commitments,
 }
 
 #[near_bindgen]
@@ -49,6 +75,47 @@ pub struct Contract {
     pub owner_id: AccountId,
 
     pub near_claim_amount: Balance,
+
+    /// Maps an alias asset ID to the asset ID it resolves to.
+    pub asset_aliases: UnorderedMap<AssetId, AssetId>,
+
+    /// Full reports keyed by asset ID and oracle ID.
+    pub reports: LookupMap<(AssetId, AccountId), Report>,
+
+    /// Queued owner actions.
+    pub proposals: UnorderedMap<ProposalId, Proposal>,
+
+    pub next_proposal_id: ProposalId,
+
+    /// The delay before a proposed owner action can be executed.
+    pub timelock_duration_sec: DurationSec,
+
+    /// Whether reporting and `oracle_call` are paused.
+    pub paused: bool,
+
+    /// The proposed owner that hasn't accepted the ownership yet.
+    pub pending_owner: Option<PendingOwner>,
+
+    /// Roles granted to accounts by the owner.
+    pub roles: UnorderedMap<AccountId, Vec<Role>>,
+
+    /// The code staged for an upgrade. The code itself is stored under `StorageKey::StagedCode`.
+    pub staged_upgrade: Option<StagedUpgrade>,
+
+    /// Set while the asset and oracle entries are rewritten by `migrate_batch`.
+    pub migration_progress: Option<MigrationProgress>,
+
+    /// sha256 of the current code, if it was deployed by `deploy_upgrade` or `rollback`.
+    pub code_hash: Option<Base58CryptoHash>,
+
+    /// The code replaced by the last upgrade, which `rollback` can redeploy.
+    pub previous_code: Option<PreviousCode>,
+
+    /// The sum of the weights of all oracles.
+    pub total_oracle_weight: u64,
+
+    /// Price commitments of commit-reveal assets in their last round, keyed by asset ID.
+    pub commitments: LookupMap<AssetId, AssetCommitments>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -58,6 +125,10 @@ pub struct PriceData {
     pub timestamp: Timestamp,
     pub recency_duration_sec: DurationSec,
 
+    /// The asset in which all prices are denominated. `None` means USD.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote_asset_id: Option<AssetId>,
+
     pub prices: Vec<AssetOptionalPrice>,
 }
 
@@ -73,13 +144,31 @@ impl Contract {
         recency_duration_sec: DurationSec,
         owner_id: AccountId,
         near_claim_amount: U128,
+        timelock_duration_sec: Option<DurationSec>,
     ) -> Self {
+        let mut roles = UnorderedMap::new(StorageKey::Roles);
+        roles.insert(&owner_id, &Role::ALL.to_vec());
+        migration::write_schema_version(SCHEMA_VERSION);
         Self {
             oracles: UnorderedMap::new(StorageKey::Oracles),
             assets: UnorderedMap::new(StorageKey::Assets),
             recency_duration_sec,
             owner_id,
             near_claim_amount: near_claim_amount.into(),
+            asset_aliases: UnorderedMap::new(StorageKey::AssetAliases),
+            reports: LookupMap::new(StorageKey::Reports),
+            proposals: UnorderedMap::new(StorageKey::Proposals),
+            next_proposal_id: 0,
+            timelock_duration_sec: timelock_duration_sec.unwrap_or(DEFAULT_TIMELOCK_DURATION_SEC),
+            paused: false,
+            pending_owner: None,
+            roles,
+            staged_upgrade: None,
+            migration_progress: None,
+            code_hash: None,
+            previous_code: None,
+            total_oracle_weight: 0,
+            commitments: LookupMap::new(StorageKey::Commitments),
         }
     }
 
@@ -88,7 +177,7 @@ impl Contract {
         assert!(self.internal_get_oracle(&account_id).is_none());
         for asset_id in asset_ids {
             let mut asset = self.internal_get_asset(&asset_id).expect("Unknown asset");
-            if asset.remove_report(&account_id) {
+            if self.internal_remove_report(&asset_id, &mut asset, &account_id) {
                 self.internal_set_asset(&asset_id, asset);
             }
         }
@@ -111,49 +200,57 @@ impl Contract {
     }
 
     pub fn get_asset(&self, asset_id: AssetId) -> Option<Asset> {
-        self.internal_get_asset(&asset_id)
+        self.internal_get_asset(&self.internal_resolve_asset_id(&asset_id))
     }
 
-    pub fn get_price_data(&self, asset_ids: Option<Vec<AssetId>>) -> PriceData {
-        let asset_ids = asset_ids.unwrap_or_else(|| self.assets.keys().collect());
-        let timestamp = env::block_timestamp();
-        let timestamp_cut = timestamp.saturating_sub(to_nano(self.recency_duration_sec));
-        let min_num_recent_reports = std::cmp::max(1, (self.oracles.len() + 1) / 2) as usize;
+    /// Returns reports of the current oracles for the given asset.
+    pub fn get_asset_reports(
+        &self,
+        asset_id: AssetId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<Report> {
+        let asset_id = self.internal_resolve_asset_id(&asset_id);
+        let oracle_ids = self.oracles.keys_as_vector();
+        let from_index = from_index.unwrap_or(0);
+        let limit = limit.unwrap_or(oracle_ids.len());
+        (from_index..std::cmp::min(oracle_ids.len(), from_index + limit))
+            .filter_map(|index| {
+                self.internal_get_report(&asset_id, &oracle_ids.get(index).unwrap())
+            })
+            .collect()
+    }
 
-        PriceData {
-            timestamp,
-            recency_duration_sec: self.recency_duration_sec,
-            prices: asset_ids
-                .into_iter()
-                .map(|asset_id| {
-                    // EMA for a specific asset, e.g. wrap.near#3600 is 1 hour EMA for wrap.near
-                    if let Some((base_asset_id, period_sec)) = asset_id.split_once('#') {
-                        let period_sec: DurationSec =
-                            period_sec.parse().expect("Failed to parse EMA period");
-                        let asset = self.internal_get_asset(&base_asset_id.to_string());
-                        AssetOptionalPrice {
-                            asset_id,
-                            price: asset.and_then(|asset| {
-                                asset
-                                    .emas
-                                    .into_iter()
-                                    .find(|ema| ema.period_sec == period_sec)
-                                    .filter(|ema| ema.timestamp >= timestamp_cut)
-                                    .and_then(|ema| ema.price)
-                            }),
-                        }
-                    } else {
-                        let asset = self.internal_get_asset(&asset_id);
-                        AssetOptionalPrice {
-                            asset_id,
-                            price: asset.and_then(|asset| {
-                                asset.median_price(timestamp_cut, min_num_recent_reports)
-                            }),
-                        }
-                    }
-                })
-                .collect(),
-        }
+    pub fn get_asset_aliases(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<(AssetId, AssetId)> {
+        unordered_map_pagination(&self.asset_aliases, from_index, limit)
+    }
+
+    /// Returns price data for the given list of asset IDs.
+    /// Consumers may pass their own `recency_duration_sec` and `min_num_recent_reports`. The
+    /// stricter of the given values and the contract config is used, so a consumer can only
+    /// tighten the requirements. For assets with a weighted median, `min_num_recent_reports` is
+    /// the minimum total weight of recent reports.
+    /// If `quote_asset_id` is given, every price (including EMAs) is divided by the median price
+    /// of the quote asset, see `Price::checked_div` for precision. If the quote asset price is not
+    /// available, then all prices are `None`. Volatilities (e.g. `wrap.near%86400`), spread and
+    /// pair EMAs (e.g. `wrap.near#3600:spread:600`) are ratios and are never divided.
+    pub fn get_price_data(
+        &self,
+        asset_ids: Option<Vec<AssetId>>,
+        recency_duration_sec: Option<DurationSec>,
+        min_num_recent_reports: Option<u32>,
+        quote_asset_id: Option<AssetId>,
+    ) -> PriceData {
+        self.internal_get_price_data(
+            asset_ids,
+            recency_duration_sec,
+            min_num_recent_reports,
+            quote_asset_id,
+        )
     }
 
     /// Returns price data for a given oracle ID and given list of asset IDs.
@@ -174,28 +271,34 @@ impl Contract {
         PriceData {
             timestamp,
             recency_duration_sec,
+            quote_asset_id: None,
             prices: asset_ids
                 .into_iter()
                 .map(|asset_id| {
-                    let asset = self.internal_get_asset(&asset_id);
+                    let report = self.internal_get_report(
+                        &self.internal_resolve_asset_id(&asset_id),
+                        &oracle_id,
+                    );
                     AssetOptionalPrice {
                         asset_id,
-                        price: asset.and_then(|asset| {
-                            asset
-                                .reports
-                                .into_iter()
-                                .find(|report| report.oracle_id == oracle_id)
-                                .filter(|report| report.timestamp >= timestamp_cut)
-                                .map(|report| report.price)
-                        }),
+                        price: report
+                            .filter(|report| report.timestamp >= timestamp_cut)
+                            .map(|report| report.price),
                     }
                 })
                 .collect(),
         }
     }
 
-    pub fn report_prices(&mut self, prices: Vec<AssetPrice>, claim_near: Option<bool>) {
+    /// Returns the status of every reported price in the same order. Problems with a single price
+    /// don't fail the call.
+    pub fn report_prices(
+        &mut self,
+        prices: Vec<AssetPrice>,
+        claim_near: Option<bool>,
+    ) -> Vec<ReportStatus> {
         assert!(!prices.is_empty());
+        self.assert_not_paused();
         let oracle_id = env::predecessor_account_id();
         let timestamp = env::block_timestamp();
 
@@ -214,36 +317,95 @@ impl Contract {
             }
         }
 
+        // Updating prices
+        let mut reported_asset_ids = Vec::with_capacity(prices.len());
+        let statuses = prices
+            .into_iter()
+            .map(|AssetPrice { asset_id, price }| {
+                let asset_id = self.internal_resolve_asset_id(&asset_id);
+                let status = self.internal_report_price(
+                    &asset_id,
+                    Report {
+                        oracle_id: oracle_id.clone(),
+                        timestamp,
+                        price,
+                    },
+                    oracle.weight,
+                    &reported_asset_ids,
+                    false,
+                );
+                match status {
+                    ReportStatus::OutOfBounds | ReportStatus::NotWhitelisted => {
+                        oracle.rejected_reports += 1
+                    }
+                    ReportStatus::UnknownAsset => {
+                        log!("Warning! Unknown asset ID: {}", asset_id);
+                    }
+                    ReportStatus::Duplicate => {
+                        log!("Warning! Duplicate asset ID: {}", asset_id);
+                    }
+                    ReportStatus::CommitRevealRequired => {
+                        log!("Warning! Commit-reveal is required for {}", asset_id);
+                    }
+                    _ => {}
+                }
+                reported_asset_ids.push(asset_id);
+                status
+            })
+            .collect();
+
         self.internal_set_oracle(&oracle_id, oracle);
+        statuses
+    }
 
-        // Updating prices
-        for AssetPrice { asset_id, price } in prices {
-            price.assert_valid();
-            if let Some(mut asset) = self.internal_get_asset(&asset_id) {
-                asset.remove_report(&oracle_id);
-                asset.add_report(Report {
-                    oracle_id: oracle_id.clone(),
+    /// Refreshes the timestamps of the oracle's reports for the given assets without changing
+    /// their prices. Only recent reports can be confirmed. Returns the status of every asset in
+    /// the same order.
+    pub fn confirm_prices(&mut self, asset_ids: Vec<AssetId>) -> Vec<ReportStatus> {
+        assert!(!asset_ids.is_empty());
+        self.assert_not_paused();
+        let oracle_id = env::predecessor_account_id();
+        let timestamp = env::block_timestamp();
+
+        let mut oracle = self.internal_get_oracle(&oracle_id).expect("Not an oracle");
+        oracle.last_report = timestamp;
+
+        let timestamp_cut = timestamp.saturating_sub(to_nano(self.recency_duration_sec));
+        let mut confirmed_asset_ids = Vec::with_capacity(asset_ids.len());
+        let statuses = asset_ids
+            .into_iter()
+            .map(|asset_id| {
+                let asset_id = self.internal_resolve_asset_id(&asset_id);
+                let status = self.internal_confirm_price(
+                    &oracle_id,
+                    &asset_id,
                     timestamp,
-                    price,
-                });
-                if !asset.emas.is_empty() {
-                    let timestamp_cut =
-                        timestamp.saturating_sub(to_nano(self.recency_duration_sec));
-                    let min_num_recent_reports =
-                        std::cmp::max(1, (self.oracles.len() + 1) / 2) as usize;
-                    if let Some(median_price) =
-                        asset.median_price(timestamp_cut, min_num_recent_reports)
-                    {
-                        for ema in asset.emas.iter_mut() {
-                            ema.recompute(median_price, timestamp);
-                        }
+                    timestamp_cut,
+                    oracle.weight,
+                    &confirmed_asset_ids,
+                );
+                match status {
+                    ReportStatus::UnknownAsset => {
+                        log!("Warning! Unknown asset ID: {}", asset_id);
+                    }
+                    ReportStatus::Duplicate => {
+                        log!("Warning! Duplicate asset ID: {}", asset_id);
                     }
+                    ReportStatus::NotWhitelisted | ReportStatus::MissingReport => {
+                        log!("Warning! No price to confirm for {}", asset_id);
+                    }
+                    ReportStatus::CommitRevealRequired => {
+                        log!("Warning! Commit-reveal is required for {}", asset_id);
+                    }
+                    _ => {}
                 }
-                self.internal_set_asset(&asset_id, asset);
-            } else {
-                log!("Warning! Unknown asset ID: {}", asset_id);
-            }
-        }
+                confirmed_asset_ids.push(asset_id);
+                status
+            })
+            .collect();
+
+        self.internal_set_oracle(&oracle_id, oracle);
+        statuses
     }
 
     #[payable]
@@ -252,11 +414,20 @@ impl Contract {
         receiver_id: AccountId,
         asset_ids: Option<Vec<AssetId>>,
         msg: String,
+        recency_duration_sec: Option<DurationSec>,
+        min_num_recent_reports: Option<u32>,
+        quote_asset_id: Option<AssetId>,
     ) -> Promise {
         self.assert_well_paid();
+        self.assert_not_paused();
 
         let sender_id = env::predecessor_account_id();
-        let price_data = self.get_price_data(asset_ids);
+        let price_data = self.internal_get_price_data(
+            asset_ids,
+            recency_duration_sec,
+            min_num_recent_reports,
+            quote_asset_id,
+        );
         let remaining_gas = env::prepaid_gas() - env::used_gas();
         assert!(remaining_gas >= GAS_FOR_PROMISE);
 
@@ -272,7 +443,233 @@ impl Contract {
 }
 
 impl Contract {
+    fn internal_report_price(
+        &mut self,
+        asset_id: &AssetId,
+        report: Report,
+        weight: u32,
+        reported_asset_ids: &[AssetId],
+        revealed: bool,
+    ) -> ReportStatus {
+        let oracle_id = &report.oracle_id;
+        let timestamp = report.timestamp;
+        let timestamp_cut = timestamp.saturating_sub(to_nano(self.recency_duration_sec));
+        let mut asset = if let Some(asset) = self.internal_get_asset(asset_id) {
+            asset
+        } else {
+            return ReportStatus::UnknownAsset;
+        };
+        if reported_asset_ids.contains(asset_id) {
+            return ReportStatus::Duplicate;
+        }
+        if !asset.is_oracle_allowed(oracle_id) {
+            log!("Warning! Rejected price for {}: not whitelisted", asset_id);
+            return ReportStatus::NotWhitelisted;
+        }
+        if asset.commit_reveal.is_some() && !revealed {
+            return ReportStatus::CommitRevealRequired;
+        }
+        let previous_price = self
+            .internal_get_report(asset_id, oracle_id)
+            .filter(|report| report.timestamp >= timestamp_cut)
+            .map(|report| report.price);
+        let price = match Some(report.price)
+            .filter(|price| price.is_valid())
+            .ok_or_else(|| "invalid decimals".to_string())
+            .and_then(|price| {
+                asset.normalize_reported_price(price).ok_or_else(|| {
+                    format!(
+                        "can't be rescaled to {} decimals",
+                        asset.price_decimals.as_ref().unwrap().decimals
+                    )
+                })
+            })
+            .and_then(|price| {
+                asset
+                    .check_price_bounds(&price, previous_price.as_ref())
+                    .map(|_| price)
+            }) {
+            Ok(price) => price,
+            Err(reason) => {
+                log!("Warning! Rejected price for {}: {}", asset_id, reason);
+                return ReportStatus::OutOfBounds;
+            }
+        };
+        self.internal_set_report(asset_id, &mut asset, Report { price, ..report }, weight);
+        self.internal_update_median(&mut asset, timestamp_cut, timestamp);
+        self.internal_set_asset(asset_id, asset);
+        ReportStatus::Accepted
+    }
+
+    fn internal_confirm_price(
+        &mut self,
+        oracle_id: &AccountId,
+        asset_id: &AssetId,
+        timestamp: Timestamp,
+        timestamp_cut: Timestamp,
+        weight: u32,
+        confirmed_asset_ids: &[AssetId],
+    ) -> ReportStatus {
+        let mut asset = if let Some(asset) = self.internal_get_asset(asset_id) {
+            asset
+        } else {
+            return ReportStatus::UnknownAsset;
+        };
+        if confirmed_asset_ids.contains(asset_id) {
+            return ReportStatus::Duplicate;
+        }
+        if !asset.is_oracle_allowed(oracle_id) {
+            return ReportStatus::NotWhitelisted;
+        }
+        if asset.commit_reveal.is_some() {
+            return ReportStatus::CommitRevealRequired;
+        }
+        let mut report = if let Some(report) = self.internal_get_report(asset_id, oracle_id) {
+            report
+        } else {
+            return ReportStatus::MissingReport;
+        };
+        if report.timestamp < timestamp_cut {
+            return ReportStatus::MissingReport;
+        }
+        report.timestamp = timestamp;
+        self.internal_set_report(asset_id, &mut asset, report, weight);
+        self.internal_update_median(&mut asset, timestamp_cut, timestamp);
+        self.internal_set_asset(asset_id, asset);
+        ReportStatus::Accepted
+    }
+
     pub fn assert_well_paid(&self) {
         assert_one_yocto();
     }
+
+    pub fn default_min_num_recent_reports(&self) -> usize {
+        std::cmp::max(1, (self.oracles.len() + 1) / 2) as usize
+    }
+
+    /// The default minimum number of recent reports, or half of the total oracle weight (rounded
+    /// up) for assets with a weighted median.
+    pub fn default_quorum(&self, asset: &Asset) -> usize {
+        if asset.aggregation == Some(Aggregation::WeightedMedian) {
+            std::cmp::max(1, self.total_oracle_weight - self.total_oracle_weight / 2) as usize
+        } else {
+            self.default_min_num_recent_reports()
+        }
+    }
+
+    /// Recomputes the asset median. Pair EMAs use the last median of the quote asset if it's
+    /// recent.
+    fn internal_update_median(
+        &self,
+        asset: &mut Asset,
+        timestamp_cut: Timestamp,
+        timestamp: Timestamp,
+    ) {
+        let min_num_recent_reports = self.default_quorum(asset);
+        asset.update_median(
+            timestamp_cut,
+            min_num_recent_reports,
+            timestamp,
+            &|quote_asset_id| {
+                self.internal_get_asset(&self.internal_resolve_asset_id(quote_asset_id))
+                    .filter(|quote| quote.last_median_timestamp >= timestamp_cut)
+                    .and_then(|quote| quote.last_median)
+            },
+        );
+    }
+
+    pub fn internal_get_price_data(
+        &self,
+        asset_ids: Option<Vec<AssetId>>,
+        recency_duration_sec: Option<DurationSec>,
+        min_num_recent_reports: Option<u32>,
+        quote_asset_id: Option<AssetId>,
+    ) -> PriceData {
+        let asset_ids = asset_ids.unwrap_or_else(|| self.assets.keys().collect());
+        let timestamp = env::block_timestamp();
+        let recency_duration_sec = recency_duration_sec
+            .map(|d| std::cmp::min(d, self.recency_duration_sec))
+            .unwrap_or(self.recency_duration_sec);
+        let timestamp_cut = timestamp.saturating_sub(to_nano(recency_duration_sec));
+        let min_num_recent_reports = min_num_recent_reports.unwrap_or(0) as usize;
+        let median_price = |asset: &Asset| {
+            asset.median_price(
+                timestamp_cut,
+                std::cmp::max(self.default_quorum(asset), min_num_recent_reports),
+            )
+        };
+        let quote_price = quote_asset_id.as_ref().map(|quote_asset_id| {
+            self.internal_get_asset(&self.internal_resolve_asset_id(quote_asset_id))
+                .and_then(|asset| median_price(&asset))
+        });
+
+        PriceData {
+            timestamp,
+            recency_duration_sec,
+            quote_asset_id,
+            prices: asset_ids
+                .into_iter()
+                .map(|asset_id| {
+                    // EMA for a specific asset, e.g. wrap.near#3600 is 1 hour EMA for wrap.near
+                    if let Some((base_asset_id, ema_key)) = asset_id.split_once('#') {
+                        // A malformed period has no price.
+                        let price = ema_key
+                            .split(':')
+                            .next()
+                            .unwrap()
+                            .parse::<DurationSec>()
+                            .ok()
+                            .and_then(|_| {
+                                self.internal_get_asset(
+                                    &self.internal_resolve_asset_id(&base_asset_id.to_string()),
+                                )?
+                                .ema_price(ema_key, timestamp_cut)
+                            });
+                        AssetOptionalPrice { asset_id, price }
+                    } else if let Some((base_asset_id, period_sec)) = asset_id.split_once('%') {
+                        // Volatility for a specific asset, e.g. wrap.near%86400 is over 1 day.
+                        // A malformed period has no price.
+                        let price = period_sec
+                            .parse::<DurationSec>()
+                            .ok()
+                            .and_then(|period_sec| {
+                                self.internal_get_asset(
+                                    &self.internal_resolve_asset_id(&base_asset_id.to_string()),
+                                )?
+                                .volatility_price(period_sec, timestamp_cut)
+                            });
+                        AssetOptionalPrice { asset_id, price }
+                    } else {
+                        let asset =
+                            self.internal_get_asset(&self.internal_resolve_asset_id(&asset_id));
+                        AssetOptionalPrice {
+                            asset_id,
+                            price: asset.and_then(|asset| {
+                                median_price(&asset).map(|price| asset.to_canonical_price(price))
+                            }),
+                        }
+                    }
+                })
+                .map(
+                    |AssetOptionalPrice { asset_id, price }| AssetOptionalPrice {
+                        price: match &quote_price {
+                            None => price,
+                            // Volatilities, spread and pair EMAs are not asset prices.
+                            Some(_)
+                                if asset_id.contains('%')
+                                    || matches!(asset_id.split_once('#'),
+                                        Some((_, ema_key)) if is_ratio_ema_key(ema_key)) =>
+                            {
+                                price
+                            }
+                            Some(quote_price) => price.and_then(|price| {
+                                quote_price.and_then(|quote_price| price.checked_div(&quote_price))
+                            }),
+                        },
+                        asset_id,
+                    },
+                )
+                .collect(),
+        }
+    }
 }
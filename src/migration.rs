@@ -0,0 +1,236 @@
+use crate::*;
+use near_sdk::IntoStorageKey;
+
+/// Ordered state migrations. The step at index `i` migrates the top-level state from the schema
+/// version `i` to `i + 1`. Each step reads the state layout of its version and writes the next one.
+/// The schema version `0` is the state of 0.6.0 and earlier, which didn't store the version.
+const MIGRATIONS: &[fn()] = &[migrate_from_0_6_0];
+
+/// Ordered state rollbacks. The step at index `i` migrates the state from the schema version
+/// `i + 1` back to `i`, so the previous code can read it.
+const ROLLBACKS: &[fn()] = &[rollback_to_0_6_0];
+
+/// sha256 of the released 0.6.0 code, which is replaced by the migration from its state.
+const CODE_HASH_0_6_0: &str = "4sUttxKK4gJpWr1mhfNddBt497ZiXsRMbTYvCeQQbjR2";
+
+/// The schema version of the state written by this code.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+const DEFAULT_MIGRATE_BATCH_LIMIT: u64 = 50;
+
+/// Converts the 0.6.0 state. Reports are moved out of the assets by `migrate_batch`.
+fn migrate_from_0_6_0() {
+    let contract: ContractV0 = env::state_read().expect("Failed to read the 0.6.0 state");
+    let mut contract: Contract = contract.into();
+    contract.migration_progress = Some(MigrationProgress::default());
+    contract.previous_code = Some(PreviousCode {
+        code_hash: CODE_HASH_0_6_0.parse().unwrap(),
+        schema_version: 0,
+        replaced_at: env::block_timestamp(),
+    });
+    env::state_write(&contract);
+}
+
+/// Writes the 0.6.0 state back. Assets get their full reports again, oracles lose the new stats
+/// and the collections added since 0.6.0 are cleared. Reports of removed oracles that were not cleaned are dropped.
+fn rollback_to_0_6_0() {
+    let mut contract: Contract = env::state_read().expect("Failed to read the state");
+    let oracle_ids: Vec<AccountId> = contract.oracles.keys().collect();
+    for (asset_id, v) in contract.assets.to_vec() {
+        let asset: AssetV1 = match v {
+            VAsset::V0(a) => a.into(),
+            VAsset::V1(a) => a,
+            VAsset::Current(a) => {
+                contract.commitments.remove(&asset_id);
+                AssetV1 {
+                    emas: a
+                        .emas
+                        .iter()
+                        .filter(|ema| ema.source.is_none())
+                        .map(|ema| a.caught_up_ema(ema).into())
+                        .collect(),
+                    reports: oracle_ids
+                        .iter()
+                        .filter_map(|oracle_id| {
+                            contract
+                                .reports
+                                .remove(&(asset_id.clone(), oracle_id.clone()))
+                        })
+                        .collect(),
+                }
+            }
+        };
+        contract.assets.insert(&asset_id, &VAsset::V1(asset));
+    }
+    for (account_id, v) in contract.oracles.to_vec() {
+        let oracle: Oracle = v.into();
+        contract
+            .oracles
+            .insert(&account_id, &VOracle::V1(oracle.into()));
+    }
+    contract.asset_aliases.clear();
+    contract.proposals.clear();
+    contract.roles.clear();
+    env::storage_remove(&StorageKey::StagedCode.into_storage_key());
+    env::storage_remove(&schema_version_key());
+    env::state_write(&ContractV0 {
+        oracles: contract.oracles,
+        assets: contract.assets,
+        recency_duration_sec: contract.recency_duration_sec,
+        owner_id: contract.owner_id,
+        near_claim_amount: contract.near_claim_amount,
+    });
+}
+
+fn schema_version_key() -> Vec<u8> {
+    StorageKey::SchemaVersion.into_storage_key()
+}
+
+pub(crate) fn read_schema_version() -> u32 {
+    env::storage_read(&schema_version_key())
+        .map(|v| u32::try_from_slice(&v).expect("Invalid schema version"))
+        .unwrap_or(0)
+}
+
+pub(crate) fn write_schema_version(schema_version: u32) {
+    env::storage_write(&schema_version_key(), &schema_version.try_to_vec().unwrap());
+}
+
+/// Runs all migration steps from the stored schema version and stores the latest version.
+pub(crate) fn run_migrations() {
+    let schema_version = read_schema_version();
+    assert!(
+        schema_version <= SCHEMA_VERSION,
+        "The state schema version {} is newer than the code",
+        schema_version
+    );
+    for migration in &MIGRATIONS[schema_version as usize..] {
+        migration();
+    }
+    write_schema_version(SCHEMA_VERSION);
+}
+
+/// Forgets the previous code and migrates the state back to the schema version it reads.
+pub(crate) fn run_rollbacks() {
+    let mut contract: Contract = env::state_read().expect("Failed to read the state");
+    let previous_code = contract
+        .previous_code
+        .take()
+        .expect("No previous code to roll back to");
+    contract.code_hash = Some(previous_code.code_hash);
+    env::state_write(&contract);
+    let schema_version = read_schema_version();
+    assert!(
+        previous_code.schema_version <= schema_version,
+        "The previous code reads a newer schema version"
+    );
+    for rollback in ROLLBACKS[previous_code.schema_version as usize..schema_version as usize]
+        .iter()
+        .rev()
+    {
+        rollback();
+    }
+    // The schema version isn't stored before the schema version 1.
+    if previous_code.schema_version > 0 {
+        write_schema_version(previous_code.schema_version);
+    }
+}
+
+/// Progress of rewriting the asset and oracle entries to their latest versions.
+#[derive(BorshSerialize, BorshDeserialize, Default)]
+pub struct MigrationProgress {
+    pub next_asset_index: u64,
+    pub next_oracle_index: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MigrationStatus {
+    pub schema_version: u32,
+    /// Whether some asset or oracle entries are not migrated yet. Reports and assets can't be
+    /// modified until the migration completes.
+    pub in_progress: bool,
+    pub migrated_assets: u64,
+    pub num_assets: u64,
+    pub migrated_oracles: u64,
+    pub num_oracles: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Rewrites up to `limit` asset and oracle entries to their latest versions, assets first.
+    /// Legacy asset reports are moved into `reports`. Can be called by anyone until the migration
+    /// completes.
+    pub fn migrate_batch(&mut self, limit: Option<u64>) -> MigrationStatus {
+        let mut progress = self
+            .migration_progress
+            .take()
+            .expect("No migration in progress");
+        let mut limit = limit.unwrap_or(DEFAULT_MIGRATE_BATCH_LIMIT);
+        while limit > 0 && progress.next_asset_index < self.assets.len() {
+            let asset_id = self
+                .assets
+                .keys_as_vector()
+                .get(progress.next_asset_index)
+                .unwrap();
+            self.internal_migrate_asset(&asset_id);
+            progress.next_asset_index += 1;
+            limit -= 1;
+        }
+        while limit > 0 && progress.next_oracle_index < self.oracles.len() {
+            let account_id = self
+                .oracles
+                .keys_as_vector()
+                .get(progress.next_oracle_index)
+                .unwrap();
+            let oracle: Oracle = self.oracles.get(&account_id).unwrap().into();
+            self.internal_set_oracle(&account_id, oracle);
+            progress.next_oracle_index += 1;
+            limit -= 1;
+        }
+        if progress.next_asset_index < self.assets.len()
+            || progress.next_oracle_index < self.oracles.len()
+        {
+            self.migration_progress = Some(progress);
+        }
+        self.get_migration_status()
+    }
+
+    pub fn get_migration_status(&self) -> MigrationStatus {
+        let (migrated_assets, migrated_oracles) = match &self.migration_progress {
+            Some(progress) => (progress.next_asset_index, progress.next_oracle_index),
+            None => (self.assets.len(), self.oracles.len()),
+        };
+        MigrationStatus {
+            schema_version: read_schema_version(),
+            in_progress: self.migration_progress.is_some(),
+            migrated_assets,
+            num_assets: self.assets.len(),
+            migrated_oracles,
+            num_oracles: self.oracles.len(),
+        }
+    }
+}
+
+impl Contract {
+    pub fn assert_migrated(&self) {
+        assert!(
+            self.migration_progress.is_none(),
+            "The state migration is in progress"
+        );
+    }
+
+    /// Moves reports of a legacy asset into `reports` and rewrites the asset with the cache.
+    fn internal_migrate_asset(&mut self, asset_id: &AssetId) {
+        let asset: AssetV1 = match self.assets.get(asset_id).unwrap() {
+            VAsset::V0(a) => a.into(),
+            VAsset::V1(a) => a,
+            VAsset::Current(_) => return,
+        };
+        for report in &asset.reports {
+            self.reports
+                .insert(&(asset_id.clone(), report.oracle_id.clone()), report);
+        }
+        self.assets.insert(asset_id, &VAsset::Current(asset.into()));
+    }
+}
@@ -1,5 +1,8 @@
 use crate::*;
 
+/// The weight of a new oracle in the weighted median.
+pub const DEFAULT_ORACLE_WEIGHT: u32 = 1;
+
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct Oracle {
@@ -9,6 +12,16 @@ pub struct Oracle {
 
     #[serde(with = "u64_dec_format")]
     pub last_near_claim: Timestamp,
+
+    /// The number of reported prices that were rejected by the asset checks.
+    pub rejected_reports: u64,
+
+    /// The number of commitments that were never revealed. Counted on the first commit or reveal
+    /// of the asset in a later round.
+    pub unrevealed_commitments: u64,
+
+    /// The weight of the oracle reports in the weighted median of assets that use it.
+    pub weight: u32,
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
@@ -22,6 +35,7 @@ pub struct OracleV0 {
 #[derive(BorshSerialize, BorshDeserialize)]
 pub enum VOracle {
     V0(OracleV0),
+    V1(OracleV1),
     Current(Oracle),
 }
 
@@ -32,7 +46,11 @@ impl From<VOracle> for Oracle {
                 last_report: o.last_report,
                 price_reports: o.price_reports,
                 last_near_claim: 0,
+                rejected_reports: 0,
+                unrevealed_commitments: 0,
+                weight: DEFAULT_ORACLE_WEIGHT,
             },
+            VOracle::V1(o) => o.into(),
             VOracle::Current(c) => c,
         }
     }
@@ -50,6 +68,9 @@ impl Oracle {
             last_report: 0,
             price_reports: 0,
             last_near_claim: 0,
+            rejected_reports: 0,
+            unrevealed_commitments: 0,
+            weight: DEFAULT_ORACLE_WEIGHT,
         }
     }
 }
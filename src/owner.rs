@@ -1,68 +1,144 @@
 use crate::*;
 use near_sdk::json_types::U128;
 
+/// How long the proposed owner can accept the ownership after the timelock delay.
+const OWNER_PROPOSAL_DURATION: Duration = 7 * 24 * 60 * 60 * 10u64.pow(9);
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingOwner {
+    pub account_id: AccountId,
+    /// The ownership can't be accepted before this timestamp.
+    #[serde(with = "u64_dec_format")]
+    pub acceptable_at: Timestamp,
+    /// The ownership can't be accepted after this timestamp.
+    #[serde(with = "u64_dec_format")]
+    pub expires_at: Timestamp,
+}
+
 #[near_bindgen]
 impl Contract {
-    #[payable]
-    pub fn set_recency_duration_sec(&mut self, recency_duration_sec: DurationSec) {
-        assert_one_yocto();
-        self.assert_owner();
-        self.recency_duration_sec = recency_duration_sec;
-    }
-
+    /// Requires a deposit to cover the oracle storage and its future reports for all assets.
     #[payable]
     pub fn add_oracle(&mut self, account_id: AccountId) {
-        assert_one_yocto();
-        self.assert_owner();
+        self.assert_role(Role::OracleManager);
+        let initial_storage_usage = env::storage_usage();
         assert!(self.internal_get_oracle(&account_id).is_none());
-        self.internal_set_oracle(&account_id, Oracle::new());
+        let oracle = Oracle::new();
+        self.total_oracle_weight += oracle.weight as u64;
+        self.internal_set_oracle(&account_id, oracle);
+        self.internal_charge_storage(
+            initial_storage_usage,
+            self.internal_oracle_reports_storage_usage(&account_id),
+        );
     }
 
+    /// Sets the weight of the oracle reports in weighted medians. Reports that are already cached
+    /// keep the previous weight until the oracle reports again.
     #[payable]
-    pub fn remove_oracle(&mut self, account_id: AccountId) {
+    pub fn set_oracle_weight(&mut self, account_id: AccountId, weight: u32) {
         assert_one_yocto();
-        self.assert_owner();
-        assert!(self.oracles.remove(&account_id).is_some());
+        self.assert_role(Role::OracleManager);
+        let mut oracle = self
+            .internal_get_oracle(&account_id)
+            .expect("Missing an oracle");
+        self.total_oracle_weight = self.total_oracle_weight - oracle.weight as u64 + weight as u64;
+        oracle.weight = weight;
+        self.internal_set_oracle(&account_id, oracle);
     }
 
+    /// Requires a deposit to cover the asset storage and its future reports from all oracles.
     #[payable]
     pub fn add_asset(&mut self, asset_id: AssetId) {
-        assert_one_yocto();
-        self.assert_owner();
+        self.assert_role(Role::AssetManager);
+        let initial_storage_usage = env::storage_usage();
         assert!(self.internal_get_asset(&asset_id).is_none());
+        assert!(
+            self.asset_aliases.get(&asset_id).is_none(),
+            "The asset ID is an alias"
+        );
         self.internal_set_asset(&asset_id, Asset::new());
+        self.internal_charge_storage(
+            initial_storage_usage,
+            self.internal_asset_reports_storage_usage(&asset_id),
+        );
     }
 
+    /// Adds an EMA of the median, or of the given source. The source EMA of a derived EMA should
+    /// exist. The quote asset of a pair EMA is stored by its asset ID if it's given by an alias.
+    /// Requires a deposit to cover the EMA storage.
     #[payable]
-    pub fn remove_asset(&mut self, asset_id: AssetId) {
-        assert_one_yocto();
-        self.assert_owner();
-        assert!(self.assets.remove(&asset_id).is_some());
-    }
-
-    #[payable]
-    pub fn add_asset_ema(&mut self, asset_id: AssetId, period_sec: DurationSec) {
-        assert_one_yocto();
-        self.assert_owner();
+    pub fn add_asset_ema(
+        &mut self,
+        asset_id: AssetId,
+        period_sec: DurationSec,
+        source: Option<EmaSource>,
+    ) {
+        self.assert_role(Role::AssetManager);
+        let initial_storage_usage = env::storage_usage();
         let mut asset = self
             .internal_get_asset(&asset_id)
             .expect("Missing an asset");
-        if asset.emas.iter().any(|ema| ema.period_sec == period_sec) {
+        let source = self.internal_resolve_ema_source(source);
+        match source.as_ref() {
+            Some(EmaSource::Ema { period_sec }) | Some(EmaSource::Spread { period_sec }) => {
+                assert!(
+                    asset.median_ema(*period_sec).is_some(),
+                    "Missing an EMA for the source period"
+                );
+            }
+            Some(EmaSource::Pair { quote_asset_id }) => {
+                assert_ne!(
+                    quote_asset_id, &asset_id,
+                    "Can't pair the asset with itself"
+                );
+                assert!(
+                    self.internal_get_asset(quote_asset_id).is_some(),
+                    "Missing the quote asset"
+                );
+            }
+            None => {}
+        }
+        let ema = AssetEma::with_source(period_sec, source);
+        if asset.emas.iter().any(|e| e.key() == ema.key()) {
             panic!("EMA for this period already exists");
         }
-        asset.emas.push(AssetEma::new(period_sec));
+        asset.emas.push(ema);
         self.internal_set_asset(&asset_id, asset);
+        self.internal_charge_storage(initial_storage_usage, 0);
     }
 
+    /// Removes the EMA of the median, or of the given source. An EMA of the median can't be
+    /// removed while it's the source of a derived EMA.
     #[payable]
-    pub fn remove_asset_ema(&mut self, asset_id: AssetId, period_sec: DurationSec) {
+    pub fn remove_asset_ema(
+        &mut self,
+        asset_id: AssetId,
+        period_sec: DurationSec,
+        source: Option<EmaSource>,
+    ) {
         assert_one_yocto();
-        self.assert_owner();
+        self.assert_role(Role::AssetManager);
         let mut asset = self
             .internal_get_asset(&asset_id)
             .expect("Missing an asset");
+        if source.is_none() {
+            assert!(
+                asset.peg.as_ref().and_then(|peg| peg.ema_period_sec) != Some(period_sec),
+                "The EMA is used by the peg"
+            );
+            assert!(
+                !asset.emas.iter().any(|ema| match ema.source {
+                    Some(EmaSource::Ema { period_sec: p })
+                    | Some(EmaSource::Spread { period_sec: p }) => p == period_sec,
+                    _ => false,
+                }),
+                "The EMA is the source of a derived EMA"
+            );
+        }
+        let key = AssetEma::with_source(period_sec, self.internal_resolve_ema_source(source)).key();
         let last_num_emas = asset.emas.len();
-        asset.emas.retain(|ema| ema.period_sec != period_sec);
+        asset.emas.retain(|ema| ema.key() != key);
         assert!(
             asset.emas.len() < last_num_emas,
             "EMA for this period doesn't exists"
@@ -70,26 +146,381 @@ impl Contract {
         self.internal_set_asset(&asset_id, asset);
     }
 
-    pub fn get_owner_id(&self) -> AccountId {
-        self.owner_id.clone()
+    /// Sets how recent reports are combined into the asset price. Passing `None` uses the median.
+    /// Requires a deposit to cover the storage.
+    #[payable]
+    pub fn set_asset_aggregation(&mut self, asset_id: AssetId, aggregation: Option<Aggregation>) {
+        self.assert_role(Role::AssetManager);
+        let initial_storage_usage = env::storage_usage();
+        let mut asset = self
+            .internal_get_asset(&asset_id)
+            .expect("Missing an asset");
+        if let Some(aggregation) = aggregation.as_ref() {
+            aggregation.assert_valid();
+        }
+        asset.aggregation = aggregation;
+        self.internal_set_asset(&asset_id, asset);
+        self.internal_charge_storage(initial_storage_usage, 0);
     }
 
-    pub fn get_near_claim_amount(&self) -> U128 {
-        self.near_claim_amount.into()
+    /// Starts tracking the realized volatility of the asset median over the window. It's available
+    /// as `asset_id%period_sec` after the first full window. Requires a deposit to cover the
+    /// storage.
+    #[payable]
+    pub fn add_asset_volatility(&mut self, asset_id: AssetId, period_sec: DurationSec) {
+        self.assert_role(Role::AssetManager);
+        let initial_storage_usage = env::storage_usage();
+        assert!(period_sec > 0, "The period should be positive");
+        let mut asset = self
+            .internal_get_asset(&asset_id)
+            .expect("Missing an asset");
+        if asset
+            .volatilities
+            .iter()
+            .any(|v| v.period_sec == period_sec)
+        {
+            panic!("Volatility for this period already exists");
+        }
+        asset.volatilities.push(AssetVolatility::new(period_sec));
+        self.internal_set_asset(&asset_id, asset);
+        self.internal_charge_storage(initial_storage_usage, 0);
+    }
+
+    #[payable]
+    pub fn remove_asset_volatility(&mut self, asset_id: AssetId, period_sec: DurationSec) {
+        assert_one_yocto();
+        self.assert_role(Role::AssetManager);
+        let mut asset = self
+            .internal_get_asset(&asset_id)
+            .expect("Missing an asset");
+        let last_num_volatilities = asset.volatilities.len();
+        asset.volatilities.retain(|v| v.period_sec != period_sec);
+        assert!(
+            asset.volatilities.len() < last_num_volatilities,
+            "Volatility for this period doesn't exist"
+        );
+        self.internal_set_asset(&asset_id, asset);
+    }
+
+    /// Sets the canonical decimals of the asset prices. Reported prices are rescaled to them and
+    /// rejected if they lose more than `tolerance_bps` (0 by default) of the price.
+    /// Already cached reports are kept, the median and EMAs are rescaled in views.
+    /// Requires a deposit to cover the storage.
+    #[payable]
+    pub fn set_asset_decimals(
+        &mut self,
+        asset_id: AssetId,
+        decimals: u8,
+        tolerance_bps: Option<u32>,
+    ) {
+        self.assert_role(Role::AssetManager);
+        let initial_storage_usage = env::storage_usage();
+        let tolerance_bps = tolerance_bps.unwrap_or(0);
+        assert!(decimals <= MAX_VALID_DECIMALS, "Invalid decimals");
+        assert!(tolerance_bps <= MAX_BPS, "Invalid tolerance");
+        let mut asset = self
+            .internal_get_asset(&asset_id)
+            .expect("Missing an asset");
+        asset.price_decimals = Some(PriceDecimals {
+            decimals,
+            tolerance_bps,
+        });
+        self.internal_set_asset(&asset_id, asset);
+        self.internal_charge_storage(initial_storage_usage, 0);
+    }
+
+    /// Sets sanity bounds for reported prices of the asset. Reports out of the bounds are skipped
+    /// and counted as rejected in the oracle stats. The max change is checked against the previous
+    /// report of the same oracle if it's within the recency duration.
+    /// Passing no bounds removes them. Requires a deposit to cover the storage.
+    #[payable]
+    pub fn set_asset_price_bounds(
+        &mut self,
+        asset_id: AssetId,
+        min_price: Option<Price>,
+        max_price: Option<Price>,
+        max_change_bps: Option<u32>,
+    ) {
+        self.assert_role(Role::AssetManager);
+        let initial_storage_usage = env::storage_usage();
+        for price in min_price.iter().chain(max_price.iter()) {
+            price.assert_valid();
+        }
+        if let (Some(min_price), Some(max_price)) = (&min_price, &max_price) {
+            assert!(
+                min_price <= max_price,
+                "The min price is above the max price"
+            );
+        }
+        let mut asset = self
+            .internal_get_asset(&asset_id)
+            .expect("Missing an asset");
+        asset.price_bounds =
+            if min_price.is_none() && max_price.is_none() && max_change_bps.is_none() {
+                None
+            } else {
+                Some(PriceBounds {
+                    min_price,
+                    max_price,
+                    max_change_bps,
+                })
+            };
+        self.internal_set_asset(&asset_id, asset);
+        self.internal_charge_storage(initial_storage_usage, 0);
+    }
+
+    /// Restricts which oracles can report the asset. Reports from other oracles are rejected as
+    /// `NotWhitelisted`. Passing `None` allows all oracles. Requires a deposit to cover the storage.
+    #[payable]
+    pub fn set_asset_oracle_whitelist(
+        &mut self,
+        asset_id: AssetId,
+        oracle_ids: Option<Vec<AccountId>>,
+    ) {
+        self.assert_role(Role::AssetManager);
+        let initial_storage_usage = env::storage_usage();
+        let mut asset = self
+            .internal_get_asset(&asset_id)
+            .expect("Missing an asset");
+        asset.oracle_whitelist = oracle_ids;
+        self.internal_set_asset(&asset_id, asset);
+        self.internal_charge_storage(initial_storage_usage, 0);
+    }
+
+    /// Withholds EMA prices of the asset until `ema_warm_up_bps` of the EMA period has passed
+    /// since its first sample. Passing `None` disables the warm-up.
+    #[payable]
+    pub fn set_asset_ema_warm_up(&mut self, asset_id: AssetId, ema_warm_up_bps: Option<u32>) {
+        self.assert_role(Role::AssetManager);
+        let initial_storage_usage = env::storage_usage();
+        let mut asset = self
+            .internal_get_asset(&asset_id)
+            .expect("Missing an asset");
+        if let Some(ema_warm_up_bps) = ema_warm_up_bps {
+            assert!(
+                ema_warm_up_bps <= MAX_BPS,
+                "The warm-up can't be longer than the period"
+            );
+        }
+        asset.ema_warm_up_bps = ema_warm_up_bps;
+        self.internal_set_asset(&asset_id, asset);
+        self.internal_charge_storage(initial_storage_usage, 0);
+    }
+
+    /// Requires oracles to report the asset with `commit_prices` and `reveal_prices` instead of
+    /// `report_prices`. Passing `None` disables commit-reveal. Requires a deposit to cover the
+    /// storage.
+    #[payable]
+    pub fn set_asset_commit_reveal(
+        &mut self,
+        asset_id: AssetId,
+        commit_reveal: Option<CommitReveal>,
+    ) {
+        self.assert_role(Role::AssetManager);
+        let initial_storage_usage = env::storage_usage();
+        let mut asset = self
+            .internal_get_asset(&asset_id)
+            .expect("Missing an asset");
+        if let Some(commit_reveal) = commit_reveal.as_ref() {
+            commit_reveal.assert_valid();
+        }
+        asset.commit_reveal = commit_reveal;
+        self.internal_set_asset(&asset_id, asset);
+        self.internal_charge_storage(initial_storage_usage, 0);
+    }
+
+    /// Marks the asset as pegged to the target price, see `get_peg_status`. Passing `None` removes
+    /// the peg. The depeg state is reset and updated on the next report.
+    /// Requires a deposit to cover the storage.
+    #[payable]
+    pub fn set_asset_peg(&mut self, asset_id: AssetId, peg: Option<Peg>) {
+        self.assert_role(Role::AssetManager);
+        let initial_storage_usage = env::storage_usage();
+        let mut asset = self
+            .internal_get_asset(&asset_id)
+            .expect("Missing an asset");
+        if let Some(peg) = peg.as_ref() {
+            peg.target_price.assert_valid();
+            assert!(
+                peg.target_price.multiplier > 0,
+                "The target price should be positive"
+            );
+            if let Some(period_sec) = peg.ema_period_sec {
+                assert!(
+                    asset.median_ema(period_sec).is_some(),
+                    "Missing an EMA for the period"
+                );
+            }
+        }
+        asset.peg = peg;
+        asset.depeg_started_at = None;
+        self.internal_set_asset(&asset_id, asset);
+        self.internal_charge_storage(initial_storage_usage, 0);
     }
 
+    /// Makes `alias_id` resolve to `asset_id` in price queries and reports.
+    /// Requires a deposit to cover the alias storage.
     #[payable]
-    pub fn update_near_claim_amount(&mut self, near_claim_amount: U128) {
+    pub fn add_asset_alias(&mut self, alias_id: AssetId, asset_id: AssetId) {
+        self.assert_role(Role::AssetManager);
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            self.internal_get_asset(&alias_id).is_none(),
+            "The alias is an existing asset"
+        );
+        assert!(
+            self.internal_get_asset(&asset_id).is_some(),
+            "Missing an asset"
+        );
+        assert!(
+            self.asset_aliases.insert(&alias_id, &asset_id).is_none(),
+            "The alias already exists"
+        );
+        self.internal_charge_storage(initial_storage_usage, 0);
+        events::emit::add_asset_alias(&alias_id, &asset_id);
+    }
+
+    #[payable]
+    pub fn remove_asset_alias(&mut self, alias_id: AssetId) {
+        assert_one_yocto();
+        self.assert_role(Role::AssetManager);
+        let asset_id = self
+            .asset_aliases
+            .remove(&alias_id)
+            .expect("Missing an alias");
+        events::emit::remove_asset_alias(&alias_id, &asset_id);
+    }
+
+    /// Moves the asset with all its reports and EMAs to the new asset ID. Existing aliases and pair
+    /// EMAs quoted in the old asset ID are updated to point to the new one. Pending commitments
+    /// are dropped.
+    /// Reports of removed oracles have to be cleaned with `clean_oracle_data` first.
+    /// Requires a deposit to cover the storage if the new asset ID is longer.
+    #[payable]
+    pub fn rename_asset(&mut self, asset_id: AssetId, new_asset_id: AssetId) {
+        self.assert_role(Role::AssetManager);
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            self.internal_get_asset(&new_asset_id).is_none(),
+            "The new asset ID already exists"
+        );
+        assert!(
+            self.asset_aliases.get(&new_asset_id).is_none(),
+            "The new asset ID is an alias"
+        );
+        let asset = self
+            .internal_get_asset(&asset_id)
+            .expect("Missing an asset");
+        self.assets.remove(&asset_id);
+        // Commitments are bound to the old asset ID.
+        self.commitments.remove(&asset_id);
+        let mut num_reports = 0;
+        for oracle_id in self.oracles.keys() {
+            if let Some(report) = self.reports.remove(&(asset_id.clone(), oracle_id.clone())) {
+                self.reports
+                    .insert(&(new_asset_id.clone(), oracle_id), &report);
+                num_reports += 1;
+            }
+        }
+        assert_eq!(
+            num_reports,
+            asset.report_cache.len(),
+            "The asset has reports from removed oracles"
+        );
+        self.internal_set_asset(&new_asset_id, asset);
+        let alias_ids: Vec<AssetId> = self
+            .asset_aliases
+            .iter()
+            .filter(|(_, target_id)| target_id == &asset_id)
+            .map(|(alias_id, _)| alias_id)
+            .collect();
+        for alias_id in alias_ids {
+            self.asset_aliases.insert(&alias_id, &new_asset_id);
+        }
+        for pair_asset_id in self.internal_pair_ema_asset_ids(&asset_id) {
+            let mut pair_asset = self.internal_get_asset(&pair_asset_id).unwrap();
+            for ema in pair_asset.emas.iter_mut() {
+                if let Some(EmaSource::Pair { quote_asset_id }) = ema.source.as_mut() {
+                    if quote_asset_id == &asset_id {
+                        *quote_asset_id = new_asset_id.clone();
+                    }
+                }
+            }
+            self.internal_set_asset(&pair_asset_id, pair_asset);
+        }
+        self.internal_charge_storage(initial_storage_usage, 0);
+        events::emit::rename_asset(&asset_id, &new_asset_id);
+    }
+
+    /// Proposes a new owner. The new owner has to call `accept_ownership` after the timelock
+    /// delay and before the proposal expires. Replaces the previous pending owner.
+    #[payable]
+    pub fn propose_owner(&mut self, account_id: AccountId) {
         assert_one_yocto();
         self.assert_owner();
-        self.near_claim_amount = near_claim_amount.into();
+        let acceptable_at = env::block_timestamp() + to_nano(self.timelock_duration_sec);
+        let pending_owner = PendingOwner {
+            account_id,
+            acceptable_at,
+            expires_at: acceptable_at + OWNER_PROPOSAL_DURATION,
+        };
+        events::emit::propose_owner(&pending_owner);
+        self.pending_owner = Some(pending_owner);
     }
 
     #[payable]
-    pub fn update_owner_id(&mut self, owner_id: AccountId) {
+    pub fn cancel_owner_proposal(&mut self) {
         assert_one_yocto();
         self.assert_owner();
-        self.owner_id = owner_id;
+        let pending_owner = self.pending_owner.take().expect("No pending owner");
+        events::emit::cancel_owner_proposal(&pending_owner.account_id);
+    }
+
+    /// Called by the pending owner to become the owner. The previous owner loses all roles and the
+    /// new owner gets all of them.
+    #[payable]
+    pub fn accept_ownership(&mut self) {
+        assert_one_yocto();
+        let pending_owner = self.pending_owner.take().expect("No pending owner");
+        assert_eq!(
+            pending_owner.account_id,
+            env::predecessor_account_id(),
+            "Can only be called by the pending owner"
+        );
+        let timestamp = env::block_timestamp();
+        assert!(
+            timestamp >= pending_owner.acceptable_at,
+            "The ownership is still timelocked"
+        );
+        assert!(
+            timestamp <= pending_owner.expires_at,
+            "The owner proposal has expired"
+        );
+        events::emit::accept_ownership(&self.owner_id, &pending_owner.account_id);
+        let previous_owner_id = std::mem::replace(&mut self.owner_id, pending_owner.account_id);
+        for role in self.roles.remove(&previous_owner_id).unwrap_or_default() {
+            events::emit::revoke_role(&previous_owner_id, role);
+        }
+        let mut roles = self.roles.get(&self.owner_id).unwrap_or_default();
+        for &role in Role::ALL.iter() {
+            if !roles.contains(&role) {
+                roles.push(role);
+                events::emit::grant_role(&self.owner_id, role);
+            }
+        }
+        self.roles.insert(&self.owner_id, &roles);
+    }
+
+    pub fn get_owner_id(&self) -> AccountId {
+        self.owner_id.clone()
+    }
+
+    pub fn get_pending_owner(&self) -> Option<PendingOwner> {
+        self.pending_owner.clone()
+    }
+
+    pub fn get_near_claim_amount(&self) -> U128 {
+        self.near_claim_amount.into()
     }
 }
 
@@ -0,0 +1,99 @@
+use crate::*;
+use near_sdk::json_types::U64;
+
+/// The peg of a stablecoin. The asset is depegged when its median and EMA both deviate from the
+/// target price by more than the tolerance.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Peg {
+    pub target_price: Price,
+    pub tolerance_bps: u32,
+    /// The period of the asset EMA that has to confirm a depeg. `None` only uses the median.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ema_period_sec: Option<DurationSec>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PegStatus {
+    pub asset_id: AssetId,
+    pub peg: Peg,
+    pub median_price: Option<Price>,
+    pub ema_price: Option<Price>,
+    /// The absolute deviation of the median from the target price.
+    pub peg_deviation_bps: Option<u32>,
+    /// The absolute deviation of the EMA from the target price.
+    pub ema_deviation_bps: Option<u32>,
+    pub depegged: bool,
+    /// When the current depeg started.
+    pub depeg_started_at: Option<U64>,
+}
+
+impl Peg {
+    pub fn is_depegged(&self, median: Option<Price>, ema: Option<Price>) -> Option<bool> {
+        let deviates = |price: Price| {
+            price
+                .deviation_bps(&self.target_price)
+                .map(|deviation_bps| deviation_bps > self.tolerance_bps)
+        };
+        let median_deviates = deviates(median?)?;
+        if self.ema_period_sec.is_some() {
+            Some(median_deviates && deviates(ema?)?)
+        } else {
+            Some(median_deviates)
+        }
+    }
+}
+
+impl Asset {
+    /// Updates the depeg start from the last median. Called after the median is updated, a missing
+    /// price keeps the previous state.
+    pub fn update_peg(&mut self, timestamp: Timestamp) {
+        let peg = if let Some(peg) = self.peg.as_ref() {
+            peg
+        } else {
+            return;
+        };
+        let ema = peg
+            .ema_period_sec
+            .and_then(|period_sec| self.median_ema(period_sec))
+            .and_then(|ema| self.warmed_up_ema(ema)?.price);
+        match peg.is_depegged(self.last_median, ema) {
+            Some(true) if self.depeg_started_at.is_none() => {
+                self.depeg_started_at = Some(U64(timestamp));
+            }
+            Some(false) => self.depeg_started_at = None,
+            _ => {}
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Returns the peg status of a pegged asset. The median and EMA are checked for recency like
+    /// in `get_price_data`, while `depegged` is the state at the last report.
+    pub fn get_peg_status(&self, asset_id: AssetId) -> Option<PegStatus> {
+        let asset_id = self.internal_resolve_asset_id(&asset_id);
+        let asset = self.internal_get_asset(&asset_id)?;
+        let peg = asset.peg.clone()?;
+        let timestamp_cut =
+            env::block_timestamp().saturating_sub(to_nano(self.recency_duration_sec));
+        let median_price = asset
+            .median_price(timestamp_cut, self.default_quorum(&asset))
+            .map(|price| asset.to_canonical_price(price));
+        let ema_price = peg
+            .ema_period_sec
+            .and_then(|period_sec| asset.ema_price(&period_sec.to_string(), timestamp_cut));
+        let deviation_bps = |price: Option<Price>| price?.deviation_bps(&peg.target_price);
+        Some(PegStatus {
+            asset_id,
+            median_price,
+            ema_price,
+            peg_deviation_bps: deviation_bps(median_price),
+            ema_deviation_bps: deviation_bps(ema_price),
+            depegged: asset.depeg_started_at.is_some(),
+            depeg_started_at: asset.depeg_started_at,
+            peg,
+        })
+    }
+}
@@ -0,0 +1,102 @@
+use crate::*;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub enum Role {
+    /// Adds and removes assets, EMAs and aliases.
+    AssetManager,
+    /// Adds and removes oracles.
+    OracleManager,
+    /// Updates the recency duration and the NEAR claim amount.
+    ConfigManager,
+    /// Upgrades the contract code.
+    Upgrader,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [
+        Role::AssetManager,
+        Role::OracleManager,
+        Role::ConfigManager,
+        Role::Upgrader,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::AssetManager => "AssetManager",
+            Role::OracleManager => "OracleManager",
+            Role::ConfigManager => "ConfigManager",
+            Role::Upgrader => "Upgrader",
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Grants the role to the account. Can only be called by the owner, who is the role admin.
+    /// Requires a deposit to cover the storage.
+    #[payable]
+    pub fn grant_role(&mut self, account_id: AccountId, role: Role) {
+        self.assert_owner();
+        let initial_storage_usage = env::storage_usage();
+        let mut roles = self.roles.get(&account_id).unwrap_or_default();
+        assert!(!roles.contains(&role), "The account already has the role");
+        roles.push(role);
+        self.roles.insert(&account_id, &roles);
+        self.internal_charge_storage(initial_storage_usage, 0);
+        events::emit::grant_role(&account_id, role);
+    }
+
+    #[payable]
+    pub fn revoke_role(&mut self, account_id: AccountId, role: Role) {
+        assert_one_yocto();
+        self.assert_owner();
+        let mut roles = self.roles.get(&account_id).unwrap_or_default();
+        let initial_len = roles.len();
+        roles.retain(|r| r != &role);
+        assert!(
+            roles.len() < initial_len,
+            "The account doesn't have the role"
+        );
+        if roles.is_empty() {
+            self.roles.remove(&account_id);
+        } else {
+            self.roles.insert(&account_id, &roles);
+        }
+        events::emit::revoke_role(&account_id, role);
+    }
+
+    pub fn get_roles(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<(AccountId, Vec<Role>)> {
+        unordered_map_pagination(&self.roles, from_index, limit)
+    }
+
+    pub fn get_account_roles(&self, account_id: AccountId) -> Vec<Role> {
+        self.roles.get(&account_id).unwrap_or_default()
+    }
+
+    pub fn has_role(&self, account_id: AccountId, role: Role) -> bool {
+        self.internal_has_role(&account_id, role)
+    }
+}
+
+impl Contract {
+    pub fn internal_has_role(&self, account_id: &AccountId, role: Role) -> bool {
+        self.roles
+            .get(account_id)
+            .map(|roles| roles.contains(&role))
+            .unwrap_or(false)
+    }
+
+    pub fn assert_role(&self, role: Role) {
+        assert!(
+            self.internal_has_role(&env::predecessor_account_id(), role),
+            "Requires {} role",
+            role.name()
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use crate::*;
+use near_sdk::json_types::U64;
+use near_sdk::StorageUsage;
+
+/// Extra bytes that are charged for every storage record in addition to its key and value.
+const STORAGE_RECORD_OVERHEAD: StorageUsage = 40;
+/// Bytes of a storage key prefix of a collection.
+const STORAGE_KEY_PREFIX_LEN: StorageUsage = 1;
+/// Bytes of an entry in the report cache of the asset without the oracle ID: timestamp, price and
+/// oracle weight.
+const CACHED_REPORT_LEN: StorageUsage = 8 + 16 + 1 + 4;
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalance {
+    /// Total bytes used by the contract.
+    pub storage_usage: U64,
+    /// The balance locked for the storage.
+    pub storage_cost: U128,
+    /// The balance that is not locked for storage. NEAR rebates are paid from it.
+    pub available_balance: U128,
+
+    /// Bytes used by assets, excluding EMAs and the report cache.
+    pub assets: U64,
+    /// Bytes used by EMAs of all assets.
+    pub emas: U64,
+    /// Bytes used by oracles, excluding their reports.
+    pub oracles: U64,
+    /// Bytes used by reports of the current oracles, including the report cache of the asset.
+    pub reports: U64,
+}
+
+/// Returns the bytes used by a new entry of an `UnorderedMap`. It takes 3 records: key to index,
+/// index to key and index to value. Each index is a `u64`.
+fn unordered_map_entry_storage_usage(
+    key_len: StorageUsage,
+    value_len: StorageUsage,
+) -> StorageUsage {
+    3 * (STORAGE_RECORD_OVERHEAD + STORAGE_KEY_PREFIX_LEN + 1 + 8) + 2 * key_len + value_len
+}
+
+fn borsh_len<T: BorshSerialize>(value: &T) -> StorageUsage {
+    value.try_to_vec().unwrap().len() as StorageUsage
+}
+
+/// Returns the bytes used by a report of the given oracle for the given asset.
+pub(crate) fn report_storage_usage(asset_id: &AssetId, oracle_id: &AccountId) -> StorageUsage {
+    let oracle_id_len = 4 + oracle_id.as_str().len() as StorageUsage;
+    let key_len = STORAGE_KEY_PREFIX_LEN + 4 + asset_id.len() as StorageUsage + oracle_id_len;
+    let value_len = oracle_id_len + 8 + 17;
+    STORAGE_RECORD_OVERHEAD + key_len + value_len + oracle_id_len + CACHED_REPORT_LEN
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Returns the storage usage of the contract with a breakdown by assets, EMAs, oracles and
+    /// reports. The breakdown iterates over all assets and oracles.
+    pub fn get_storage_balance(&self) -> StorageBalance {
+        let storage_usage = env::storage_usage();
+        let storage_cost = env::storage_byte_cost() * Balance::from(storage_usage);
+
+        let mut assets = 0;
+        let mut emas = 0;
+        let mut reports = 0;
+        for (asset_id, v) in self.assets.iter() {
+            let asset_len = borsh_len(&v);
+            let asset: Asset = v.into();
+            let emas_len: StorageUsage = asset.emas.iter().map(borsh_len).sum();
+            let report_cache_len: StorageUsage = asset.report_cache.iter().map(borsh_len).sum();
+            assets += unordered_map_entry_storage_usage(borsh_len(&asset_id), asset_len)
+                - emas_len
+                - report_cache_len;
+            emas += emas_len;
+            for oracle_id in self.oracles.keys() {
+                if self.internal_get_report(&asset_id, &oracle_id).is_some() {
+                    reports += report_storage_usage(&asset_id, &oracle_id);
+                }
+            }
+        }
+        let oracles = self
+            .oracles
+            .iter()
+            .map(|(oracle_id, v)| {
+                unordered_map_entry_storage_usage(borsh_len(&oracle_id), borsh_len(&v))
+            })
+            .sum::<StorageUsage>();
+
+        StorageBalance {
+            storage_usage: storage_usage.into(),
+            storage_cost: storage_cost.into(),
+            available_balance: (env::account_balance() + env::account_locked_balance())
+                .saturating_sub(storage_cost)
+                .into(),
+            assets: assets.into(),
+            emas: emas.into(),
+            oracles: oracles.into(),
+            reports: reports.into(),
+        }
+    }
+}
+
+impl Contract {
+    /// Requires the attached deposit to cover the storage added since `initial_storage_usage`
+    /// and `reserved_storage_usage` bytes on top of it. Refunds the excess to the predecessor.
+    pub fn internal_charge_storage(
+        &self,
+        initial_storage_usage: StorageUsage,
+        reserved_storage_usage: StorageUsage,
+    ) {
+        let storage_usage =
+            env::storage_usage().saturating_sub(initial_storage_usage) + reserved_storage_usage;
+        let required_deposit = env::storage_byte_cost() * Balance::from(storage_usage);
+        let attached_deposit = env::attached_deposit();
+        assert!(
+            attached_deposit > 0,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        assert!(
+            attached_deposit >= required_deposit,
+            "Not enough deposit to cover storage. Required {} yoctoNEAR",
+            required_deposit
+        );
+        let refund = attached_deposit - required_deposit;
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+    }
+
+    /// Returns bytes that reports of all current oracles for the given asset will take.
+    pub fn internal_asset_reports_storage_usage(&self, asset_id: &AssetId) -> StorageUsage {
+        self.oracles
+            .keys()
+            .map(|oracle_id| report_storage_usage(asset_id, &oracle_id))
+            .sum()
+    }
+
+    /// Returns bytes that reports of the given oracle for all current assets will take.
+    pub fn internal_oracle_reports_storage_usage(&self, oracle_id: &AccountId) -> StorageUsage {
+        self.assets
+            .keys()
+            .map(|asset_id| report_storage_usage(&asset_id, oracle_id))
+            .sum()
+    }
+}
@@ -0,0 +1,221 @@
+use crate::*;
+
+pub type ProposalId = u64;
+
+pub const DEFAULT_TIMELOCK_DURATION_SEC: DurationSec = 24 * 60 * 60;
+
+/// Owner actions that only take effect after the timelock delay.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub enum OwnerAction {
+    SetRecencyDurationSec {
+        recency_duration_sec: DurationSec,
+    },
+    RemoveOracle {
+        account_id: AccountId,
+    },
+    /// Removes the asset with its reports and aliases. Reports of removed oracles have to be
+    /// cleaned with `clean_oracle_data` first. Pair EMAs quoted in the asset have to be removed
+    /// first.
+    RemoveAsset {
+        asset_id: AssetId,
+    },
+    UpdateNearClaimAmount {
+        near_claim_amount: U128,
+    },
+    SetTimelockDurationSec {
+        timelock_duration_sec: DurationSec,
+    },
+    Unpause,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Proposal {
+    pub action: OwnerAction,
+    #[serde(with = "u64_dec_format")]
+    pub proposed_at: Timestamp,
+    /// The proposal can't be executed before this timestamp.
+    #[serde(with = "u64_dec_format")]
+    pub executable_at: Timestamp,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Queues the owner action. It can be executed after `timelock_duration_sec`.
+    /// Requires the role of the action, see `OwnerAction::role`.
+    /// Requires a deposit to cover the proposal storage.
+    #[payable]
+    pub fn propose_action(&mut self, action: OwnerAction) -> ProposalId {
+        self.assert_action_access(&action);
+        let initial_storage_usage = env::storage_usage();
+        let proposal_id = self.next_proposal_id;
+        self.next_proposal_id += 1;
+        let timestamp = env::block_timestamp();
+        let proposal = Proposal {
+            action,
+            proposed_at: timestamp,
+            executable_at: timestamp + to_nano(self.timelock_duration_sec),
+        };
+        self.proposals.insert(&proposal_id, &proposal);
+        self.internal_charge_storage(initial_storage_usage, 0);
+        events::emit::propose_action(proposal_id, &proposal);
+        proposal_id
+    }
+
+    #[payable]
+    pub fn execute_proposal(&mut self, proposal_id: ProposalId) {
+        assert_one_yocto();
+        let proposal = self
+            .proposals
+            .get(&proposal_id)
+            .expect("Missing a proposal");
+        self.assert_action_access(&proposal.action);
+        assert!(
+            env::block_timestamp() >= proposal.executable_at,
+            "The proposal is still timelocked"
+        );
+        self.proposals.remove(&proposal_id);
+        self.internal_execute_action(proposal.action);
+        events::emit::execute_proposal(proposal_id);
+    }
+
+    #[payable]
+    pub fn cancel_proposal(&mut self, proposal_id: ProposalId) {
+        assert_one_yocto();
+        let proposal = self
+            .proposals
+            .remove(&proposal_id)
+            .expect("Missing a proposal");
+        self.assert_action_access(&proposal.action);
+        events::emit::cancel_proposal(proposal_id);
+    }
+
+    /// Emergency pause of reporting and `oracle_call`. Takes effect immediately, unlike unpausing.
+    #[payable]
+    pub fn pause(&mut self) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(!self.paused, "Already paused");
+        self.paused = true;
+        events::emit::pause();
+    }
+
+    pub fn get_proposal(&self, proposal_id: ProposalId) -> Option<Proposal> {
+        self.proposals.get(&proposal_id)
+    }
+
+    pub fn get_proposals(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<(ProposalId, Proposal)> {
+        unordered_map_pagination(&self.proposals, from_index, limit)
+    }
+
+    pub fn get_timelock_duration_sec(&self) -> DurationSec {
+        self.timelock_duration_sec
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+}
+
+impl OwnerAction {
+    /// Returns the role that can propose, execute and cancel the action.
+    /// `None` means only the owner can.
+    pub fn role(&self) -> Option<Role> {
+        match self {
+            OwnerAction::SetRecencyDurationSec { .. } => Some(Role::ConfigManager),
+            OwnerAction::RemoveOracle { .. } => Some(Role::OracleManager),
+            OwnerAction::RemoveAsset { .. } => Some(Role::AssetManager),
+            OwnerAction::UpdateNearClaimAmount { .. } => Some(Role::ConfigManager),
+            OwnerAction::SetTimelockDurationSec { .. } => None,
+            OwnerAction::Unpause => None,
+        }
+    }
+}
+
+impl Contract {
+    fn assert_action_access(&self, action: &OwnerAction) {
+        if let Some(role) = action.role() {
+            self.assert_role(role);
+        } else {
+            self.assert_owner();
+        }
+    }
+
+    pub fn assert_not_paused(&self) {
+        assert!(!self.paused, "The contract is paused");
+    }
+
+    fn internal_execute_action(&mut self, action: OwnerAction) {
+        // Removals would shift the entries that are not migrated yet.
+        self.assert_migrated();
+        match action {
+            OwnerAction::SetRecencyDurationSec {
+                recency_duration_sec,
+            } => {
+                self.recency_duration_sec = recency_duration_sec;
+            }
+            OwnerAction::RemoveOracle { account_id } => {
+                let oracle: Oracle = self
+                    .oracles
+                    .remove(&account_id)
+                    .expect("Missing an oracle")
+                    .into();
+                self.total_oracle_weight -= oracle.weight as u64;
+            }
+            OwnerAction::RemoveAsset { asset_id } => {
+                assert!(
+                    self.internal_pair_ema_asset_ids(&asset_id).is_empty(),
+                    "The asset is the quote of a pair EMA"
+                );
+                let asset: Asset = self
+                    .assets
+                    .remove(&asset_id)
+                    .expect("Missing an asset")
+                    .into();
+                self.commitments.remove(&asset_id);
+                let mut num_reports = 0;
+                for oracle_id in self.oracles.keys() {
+                    if self
+                        .reports
+                        .remove(&(asset_id.clone(), oracle_id))
+                        .is_some()
+                    {
+                        num_reports += 1;
+                    }
+                }
+                assert_eq!(
+                    num_reports,
+                    asset.report_cache.len(),
+                    "The asset has reports from removed oracles"
+                );
+                let alias_ids: Vec<AssetId> = self
+                    .asset_aliases
+                    .iter()
+                    .filter(|(_, target_id)| target_id == &asset_id)
+                    .map(|(alias_id, _)| alias_id)
+                    .collect();
+                for alias_id in alias_ids {
+                    self.asset_aliases.remove(&alias_id);
+                    events::emit::remove_asset_alias(&alias_id, &asset_id);
+                }
+            }
+            OwnerAction::UpdateNearClaimAmount { near_claim_amount } => {
+                self.near_claim_amount = near_claim_amount.into();
+            }
+            OwnerAction::SetTimelockDurationSec {
+                timelock_duration_sec,
+            } => {
+                self.timelock_duration_sec = timelock_duration_sec;
+            }
+            OwnerAction::Unpause => {
+                assert!(self.paused, "Not paused");
+                self.paused = false;
+            }
+        }
+    }
+}
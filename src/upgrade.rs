@@ -1,17 +1,139 @@
 use crate::*;
+use near_sdk::IntoStorageKey;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StagedUpgrade {
+    /// sha256 of the staged code.
+    pub code_hash: Base58CryptoHash,
+    #[serde(with = "u64_dec_format")]
+    pub staged_at: Timestamp,
+    /// The staged code can't be deployed before this timestamp.
+    #[serde(with = "u64_dec_format")]
+    pub deployable_at: Timestamp,
+}
+
+/// The code replaced by an upgrade.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PreviousCode {
+    /// sha256 of the previous code.
+    pub code_hash: Base58CryptoHash,
+    /// The state snapshot marker: the schema version the previous code reads. The rollback
+    /// migrates the state back to it.
+    pub schema_version: u32,
+    #[serde(with = "u64_dec_format")]
+    pub replaced_at: Timestamp,
+}
+
+fn staged_code_key() -> Vec<u8> {
+    StorageKey::StagedCode.into_storage_key()
+}
 
 #[near_bindgen]
 impl Contract {
+    /// Migrates the state to the schema version of this code. `deploy_upgrade` passes the hash of
+    /// the staged code, so the upgrade is only recorded if the whole deploy batch succeeds.
     #[private]
     #[init(ignore_state)]
-    pub fn migrate_state() -> Self {
-        env::state_read().unwrap()
+    pub fn migrate_state(code_hash: Option<Base58CryptoHash>) -> Self {
+        let previous_schema_version = migration::read_schema_version();
+        migration::run_migrations();
+        let mut contract: Contract = env::state_read().expect("Failed to read the migrated state");
+        if let Some(code_hash) = code_hash {
+            contract.internal_record_upgrade(code_hash, previous_schema_version);
+        }
+        contract
     }
 
     /// Returns semver of this contract.
     pub fn get_version(&self) -> String {
         env!("CARGO_PKG_VERSION").to_string()
     }
+
+    /// Panics if the contract version is not the given one. Called on the new code by
+    /// `deploy_upgrade` to revert the deployment of an unexpected version.
+    pub fn assert_version(&self, version: String) {
+        assert_eq!(version, self.get_version(), "Unexpected contract version");
+    }
+
+    pub fn get_code_hash(&self) -> Option<Base58CryptoHash> {
+        self.code_hash
+    }
+
+    pub fn get_previous_code(&self) -> Option<PreviousCode> {
+        self.previous_code.clone()
+    }
+
+    /// Called by `rollback` in the same batch before the previous code is deployed.
+    #[private]
+    pub fn rollback_state() {
+        migration::run_rollbacks();
+    }
+
+    pub fn get_staged_upgrade(&self) -> Option<StagedUpgrade> {
+        self.staged_upgrade.clone()
+    }
+
+    /// Removes the staged code.
+    #[payable]
+    pub fn cancel_staged_upgrade(&mut self) {
+        assert_one_yocto();
+        self.assert_role(Role::Upgrader);
+        let staged_upgrade = self.staged_upgrade.take().expect("No staged upgrade");
+        env::storage_remove(&staged_code_key());
+        events::emit::cancel_staged_upgrade(&staged_upgrade.code_hash);
+    }
+
+    /// Deploys the staged code and calls `migrate_state` and `assert_version` on it in the same
+    /// batch, so the deployment is reverted if the migration fails or the new code is not of the
+    /// expected `version`.
+    /// Requires the staged code to match `code_hash` and the timelock delay to pass.
+    /// The staged code is removed and the upgrade is recorded by `migrate_state`, so a failed
+    /// deployment doesn't change the state.
+    #[payable]
+    pub fn deploy_upgrade(&mut self, code_hash: Base58CryptoHash, version: String) {
+        assert_one_yocto();
+        self.assert_role(Role::Upgrader);
+        let staged_upgrade = self.staged_upgrade.as_ref().expect("No staged upgrade");
+        assert!(
+            staged_upgrade.code_hash == code_hash,
+            "The code hash doesn't match the staged code"
+        );
+        assert!(
+            env::block_timestamp() >= staged_upgrade.deployable_at,
+            "The upgrade is still timelocked"
+        );
+        upgrade::deploy_staged_code(&code_hash, &version);
+    }
+}
+
+impl Contract {
+    /// Records the deployment of the staged code. Called by `migrate_state` of the new code, after
+    /// the state is migrated from `previous_schema_version`.
+    fn internal_record_upgrade(
+        &mut self,
+        code_hash: Base58CryptoHash,
+        previous_schema_version: u32,
+    ) {
+        let staged_upgrade = self.staged_upgrade.take().expect("No staged upgrade");
+        assert!(
+            staged_upgrade.code_hash == code_hash,
+            "The code hash doesn't match the staged code"
+        );
+        env::storage_remove(&staged_code_key());
+        // The hash of code deployed without `deploy_upgrade` is unknown, e.g. after the upgrade
+        // from 0.6.0, so the previous code recorded by its migration is kept.
+        if let Some(previous_code_hash) = self.code_hash {
+            self.previous_code = Some(PreviousCode {
+                code_hash: previous_code_hash,
+                schema_version: previous_schema_version,
+                replaced_at: env::block_timestamp(),
+            });
+        }
+        self.code_hash = Some(code_hash);
+        events::emit::deploy_upgrade(&code_hash);
+    }
 }
 
 mod upgrade {
@@ -24,19 +146,134 @@ mod upgrade {
     const GAS_FOR_VERIFICATION_CALL: Gas = Gas(Gas::ONE_TERA.0 * 5);
     const MIN_GAS_FOR_MIGRATE_STATE_CALL: Gas = Gas(Gas::ONE_TERA.0 * 10);
 
-    /// Self upgrade and call migrate, optimizes gas by not loading into memory the code.
-    /// Takes as input non serialized set of bytes of the code.
+    /// Stages the code for an upgrade and records its sha256, optimizes gas by not loading into
+    /// memory the code. Takes as input non serialized set of bytes of the code.
+    /// Replaces the previously staged code. Can only be called by an account with the `Upgrader`
+    /// role. The storage of the staged code is paid from the contract balance until it's deployed
+    /// or cancelled, since `UpgradeRemote` proposals can't attach a deposit. So the `Upgrader`
+    /// can lock up to the storage cost of one maximum size contract (about 40 NEAR for 4 MB) of
+    /// the contract balance, which otherwise funds the NEAR claims of oracles.
+    #[no_mangle]
+    pub extern "C" fn stage_upgrade() {
+        env::setup_panic_hook();
+        let mut contract: Contract = env::state_read().expect("ERR_CONTRACT_IS_NOT_INITIALIZED");
+        contract.assert_role(Role::Upgrader);
+        let staged_code_key = staged_code_key();
+        unsafe {
+            sys::input(0);
+            sys::sha256(u64::MAX as _, 0, 1);
+            sys::storage_write(
+                staged_code_key.len() as _,
+                staged_code_key.as_ptr() as _,
+                u64::MAX as _,
+                0,
+                2,
+            );
+        }
+        let mut code_hash = CryptoHash::default();
+        code_hash.copy_from_slice(&env::read_register(1).unwrap());
+        let code_hash: Base58CryptoHash = code_hash.into();
+        let timestamp = env::block_timestamp();
+        contract.staged_upgrade = Some(StagedUpgrade {
+            code_hash,
+            staged_at: timestamp,
+            deployable_at: timestamp + to_nano(contract.timelock_duration_sec),
+        });
+        env::state_write(&contract);
+        events::emit::stage_upgrade(&code_hash);
+    }
+
+    /// Redeploys the code replaced by the last upgrade. Takes as input non serialized set of bytes
+    /// of the previous code, which has to match its recorded sha256. The state is migrated back
+    /// to the schema version of the previous code in the same batch, so the rollback is reverted
+    /// if the previous code can't read it. Can only be called by an account with the `Upgrader`
+    /// role. Refuses to roll back more than one schema version, e.g. to 0.6.0 which is still
+    /// recorded as the previous code after a later upgrade from the unknown code deployed by the
+    /// 0.6.0 `upgrade`.
     #[no_mangle]
-    pub extern "C" fn upgrade() {
+    pub extern "C" fn rollback() {
         env::setup_panic_hook();
         let contract: Contract = env::state_read().expect("ERR_CONTRACT_IS_NOT_INITIALIZED");
-        contract.assert_owner();
+        contract.assert_role(Role::Upgrader);
+        let previous_code = contract
+            .previous_code
+            .expect("No previous code to roll back to");
+        require!(
+            previous_code.schema_version + 1 >= migration::read_schema_version(),
+            "The previous code is more than one schema version behind"
+        );
+        unsafe {
+            sys::input(0);
+            sys::sha256(u64::MAX as _, 0, 1);
+        }
+        let code_hash: CryptoHash = previous_code.code_hash.into();
+        require!(
+            env::read_register(1).unwrap() == code_hash,
+            "The code hash doesn't match the previous code"
+        );
+        events::emit::rollback(&previous_code.code_hash);
+
         let current_account_id = env::current_account_id().as_bytes().to_vec();
-        let migrate_method_name = b"migrate_state".to_vec();
-        let verification_method_name = b"get_owner_id".to_vec();
+        let rollback_method_name = b"rollback_state".to_vec();
+        let verification_method_name = b"get_version".to_vec();
         let empty_args = b"{}".to_vec();
         unsafe {
-            sys::input(0);
+            let promise_id = sys::promise_batch_create(
+                current_account_id.len() as _,
+                current_account_id.as_ptr() as _,
+            );
+            // Gas required to complete this call.
+            let required_gas =
+                env::used_gas() + GAS_TO_COMPLETE_UPGRADE_CALL + GAS_FOR_VERIFICATION_CALL;
+            require!(
+                env::prepaid_gas() >= required_gas + MIN_GAS_FOR_MIGRATE_STATE_CALL,
+                "Not enough gas to complete state rollback"
+            );
+            let rollback_state_attached_gas = env::prepaid_gas() - required_gas;
+            // Migrating the state back with the current code.
+            sys::promise_batch_action_function_call(
+                promise_id,
+                rollback_method_name.len() as _,
+                rollback_method_name.as_ptr() as _,
+                empty_args.len() as _,
+                empty_args.as_ptr() as _,
+                0 as _,
+                rollback_state_attached_gas.0,
+            );
+            sys::promise_batch_action_deploy_contract(promise_id, u64::MAX as _, 0);
+            // Checking that the previous code can read the state.
+            sys::promise_batch_action_function_call(
+                promise_id,
+                verification_method_name.len() as _,
+                verification_method_name.as_ptr() as _,
+                empty_args.len() as _,
+                empty_args.as_ptr() as _,
+                0 as _,
+                GAS_FOR_VERIFICATION_CALL.0,
+            );
+            sys::promise_return(promise_id);
+        }
+    }
+
+    /// Self upgrade with the staged code and call migrate.
+    pub(crate) fn deploy_staged_code(code_hash: &Base58CryptoHash, version: &str) {
+        let current_account_id = env::current_account_id().as_bytes().to_vec();
+        let staged_code_key = staged_code_key();
+        let migrate_method_name = b"migrate_state".to_vec();
+        let migrate_args = near_sdk::serde_json::json!({ "code_hash": code_hash })
+            .to_string()
+            .into_bytes();
+        let verification_method_name = b"assert_version".to_vec();
+        let verification_args = near_sdk::serde_json::json!({ "version": version })
+            .to_string()
+            .into_bytes();
+        unsafe {
+            // Reads the staged code into the register 0. It's removed by `migrate_state`.
+            require!(
+                sys::storage_read(staged_code_key.len() as _, staged_code_key.as_ptr() as _, 0)
+                    == 1,
+                "Missing the staged code"
+            );
             let promise_id = sys::promise_batch_create(
                 current_account_id.len() as _,
                 current_account_id.as_ptr() as _,
@@ -55,18 +292,18 @@ mod upgrade {
                 promise_id,
                 migrate_method_name.len() as _,
                 migrate_method_name.as_ptr() as _,
-                empty_args.len() as _,
-                empty_args.as_ptr() as _,
+                migrate_args.len() as _,
+                migrate_args.as_ptr() as _,
                 0 as _,
                 migrate_state_attached_gas.0,
             );
-            // Scheduling to return config after the migration is completed.
+            // Scheduling to check the version after the migration is completed.
             sys::promise_batch_action_function_call(
                 promise_id,
                 verification_method_name.len() as _,
                 verification_method_name.as_ptr() as _,
-                empty_args.len() as _,
-                empty_args.as_ptr() as _,
+                verification_args.len() as _,
+                verification_args.as_ptr() as _,
                 0 as _,
                 GAS_FOR_VERIFICATION_CALL.0,
             );
@@ -2,7 +2,8 @@ use crate::*;
 use std::cmp::Ordering;
 
 const MAX_U128_DECIMALS: u8 = 38;
-const MAX_VALID_DECIMALS: u8 = 77;
+pub(crate) const MAX_VALID_DECIMALS: u8 = 77;
+pub const MAX_BPS: u32 = 10000;
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Copy)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
@@ -21,49 +22,186 @@ pub struct Price {
 // Price DAI { multiplier: 101, decimals: 20 }
 // 50 DAI in USD = 50 * 10**18 * 101 / 10**(20 - 18) = 505 * 10**17
 
+// Price NEAR in DAI = NEAR / DAI
+// { multiplier: 1000 * 10**k / 101, decimals: 26 - 20 + k }
+// 5 NEAR in DAI = 5 * 10**24 * 1000 * 10**k / 101 / 10**(6 + k) ~= 4.95 * 10**19
+
 impl Price {
     pub fn assert_valid(&self) {
-        assert!(self.decimals <= MAX_VALID_DECIMALS);
+        assert!(self.is_valid());
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.decimals <= MAX_VALID_DECIMALS
+    }
+
+    /// Re-denominates this price in the given quote price, e.g. NEAR price in DAI instead of USD.
+    /// The multiplier is scaled up by `10**k` with the largest `k` that fits into `u128` before
+    /// the integer division, so the result keeps at least `38 - digits(quote.multiplier)`
+    /// significant digits. The remainder of the division is truncated.
+    /// Returns `None` if the quote multiplier is 0, or if the result can't be represented with
+    /// valid decimals.
+    pub fn checked_div(&self, quote: &Price) -> Option<Price> {
+        if quote.multiplier == 0 {
+            return None;
+        }
+        let mut multiplier = self.multiplier;
+        let mut scale = 0u8;
+        while scale < MAX_U128_DECIMALS {
+            if let Some(m) = multiplier.checked_mul(10) {
+                multiplier = m;
+                scale += 1;
+            } else {
+                break;
+            }
+        }
+        let mut multiplier = multiplier / quote.multiplier;
+        let mut decimals = self.decimals as i32 + scale as i32 - quote.decimals as i32;
+        if decimals < 0 {
+            multiplier = multiplier.checked_mul(10u128.checked_pow((-decimals) as u32)?)?;
+            decimals = 0;
+        }
+        while decimals > MAX_VALID_DECIMALS as i32 {
+            multiplier /= 10;
+            decimals -= 1;
+        }
+        Some(Price {
+            multiplier,
+            decimals: decimals as u8,
+        })
+    }
+
+    /// Returns the same price with the given decimals. Scaling down truncates the multiplier.
+    /// Returns `None` if the multiplier overflows, or if the truncated part is more than
+    /// `tolerance_bps` of the price.
+    pub fn rescale(&self, decimals: u8, tolerance_bps: u32) -> Option<Price> {
+        let multiplier = if decimals >= self.decimals {
+            let diff = (decimals - self.decimals) as u32;
+            if self.multiplier == 0 {
+                0
+            } else {
+                self.multiplier.checked_mul(10u128.checked_pow(diff)?)?
+            }
+        } else {
+            let diff = (self.decimals - decimals) as u32;
+            let (multiplier, remainder) = match 10u128.checked_pow(diff) {
+                Some(factor) => (self.multiplier / factor, self.multiplier % factor),
+                None => (0, self.multiplier),
+            };
+            let tolerance_bps = std::cmp::min(tolerance_bps, MAX_BPS) as u128;
+            let max_remainder = self.multiplier / MAX_BPS as u128 * tolerance_bps
+                + self.multiplier % MAX_BPS as u128 * tolerance_bps / MAX_BPS as u128;
+            if remainder > max_remainder {
+                return None;
+            }
+            multiplier
+        };
+        Some(Price {
+            multiplier,
+            decimals,
+        })
+    }
+
+    /// Returns the absolute deviation from the target price in basis points of the target price,
+    /// saturated at `u32::MAX`. Returns `None` for a zero target or if the prices can't be scaled
+    /// to the same decimals.
+    pub fn deviation_bps(&self, target: &Price) -> Option<u32> {
+        let decimals = std::cmp::max(self.decimals, target.decimals);
+        let multiplier = self.rescale(decimals, 0)?.multiplier;
+        let target_multiplier = target.rescale(decimals, 0)?.multiplier;
+        if target_multiplier == 0 {
+            return None;
+        }
+        let diff = std::cmp::max(multiplier, target_multiplier)
+            - std::cmp::min(multiplier, target_multiplier);
+        let deviation_bps = (diff / target_multiplier)
+            .checked_mul(MAX_BPS as u128)
+            .and_then(|bps| {
+                bps.checked_add(fraction_bps(diff % target_multiplier, target_multiplier))
+            })
+            .unwrap_or(u128::MAX);
+        Some(std::cmp::min(deviation_bps, u32::MAX as u128) as u32)
+    }
+
+    /// Returns the mean of the prices with the largest decimals among them. The result is
+    /// truncated. Returns `None` for no prices or if the sum overflows.
+    pub fn checked_mean(prices: &[Price]) -> Option<Price> {
+        let decimals = prices.iter().map(|price| price.decimals).max()?;
+        let sum = prices.iter().try_fold(0u128, |sum, price| {
+            sum.checked_add(price.rescale(decimals, 0)?.multiplier)
+        })?;
+        Some(Price {
+            multiplier: sum / prices.len() as u128,
+            decimals,
+        })
+    }
+}
+
+/// Returns `numerator * MAX_BPS / denominator` rounded down for `numerator < denominator`.
+/// It's computed one decimal digit at a time, so it doesn't overflow for any denominator.
+fn fraction_bps(mut numerator: u128, denominator: u128) -> u128 {
+    let mut res = 0;
+    let mut scale = 1;
+    while scale < MAX_BPS {
+        // `numerator * 10 = digit * denominator + remainder`, adding `numerator` 10 times.
+        let mut digit = 0;
+        let mut remainder = 0;
+        for _ in 0..10 {
+            if numerator >= denominator - remainder {
+                remainder = numerator - (denominator - remainder);
+                digit += 1;
+            } else {
+                remainder += numerator;
+            }
+        }
+        res = res * 10 + digit;
+        numerator = remainder;
+        scale *= 10;
     }
+    res
 }
 
+/// Prices are compared by their exact values `multiplier / 10**decimals`, so e.g. `100e-2` equals
+/// `1e0`.
 impl PartialEq<Self> for Price {
     fn eq(&self, other: &Self) -> bool {
-        self.partial_cmp(other) == Some(Ordering::Equal)
+        self.cmp(other) == Ordering::Equal
     }
 }
 
 impl PartialOrd for Price {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Eq for Price {}
+
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
         if self.decimals < other.decimals {
-            return Some(other.cmp(self).reverse());
+            return other.cmp(self).reverse();
+        }
+        // Compares `self.multiplier` with `other.multiplier * 10**decimals_diff`.
+        if other.multiplier == 0 {
+            return self.multiplier.cmp(&0);
         }
-
         let decimals_diff = self.decimals - other.decimals;
-
         if decimals_diff > MAX_U128_DECIMALS {
-            return Some(Ordering::Less);
+            // The scaled other multiplier is at least 10**39, which doesn't fit into u128.
+            return Ordering::Less;
         }
-
         if let Some(om) = other
             .multiplier
             .checked_mul(10u128.pow(decimals_diff as u32))
         {
-            Some(self.multiplier.cmp(&om))
+            self.multiplier.cmp(&om)
         } else {
-            Some(Ordering::Less)
+            Ordering::Less
         }
     }
 }
 
-impl Eq for Price {}
-
-impl Ord for Price {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.partial_cmp(other).unwrap()
-    }
-}
-
 pub(crate) mod u128_dec_format {
     use near_sdk::serde::de;
     use near_sdk::serde::{Deserialize, Deserializer, Serializer};
@@ -128,3 +266,216 @@ where
         .map(|index| (keys.get(index).unwrap(), values.get(index).unwrap().into()))
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::serde_json::{self, json};
+    use proptest::prelude::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(crate = "near_sdk::serde")]
+    struct U64Dec(#[serde(with = "u64_dec_format")] u64);
+
+    fn price(multipliers: impl Strategy<Value = u128>) -> impl Strategy<Value = Price> {
+        (multipliers, 0..=MAX_VALID_DECIMALS).prop_map(|(multiplier, decimals)| Price {
+            multiplier,
+            decimals,
+        })
+    }
+
+    #[test]
+    fn test_price_cmp_different_decimals() {
+        let p = |multiplier, decimals| Price {
+            multiplier,
+            decimals,
+        };
+        assert_eq!(p(100, 2), p(1, 0));
+        assert!(p(0, 77) < p(1, 0));
+        assert_eq!(p(0, 77), p(0, 0));
+        assert!(p(1, 77) > p(0, 0));
+        assert!(p(u128::MAX, 77) < p(1, 0));
+        assert!(p(u128::MAX, 0) > p(u128::MAX, 1));
+    }
+
+    #[test]
+    fn test_price_rescale() {
+        let p = |multiplier, decimals| Price {
+            multiplier,
+            decimals,
+        };
+        let rescale = |price: Price, decimals, tolerance_bps| {
+            price
+                .rescale(decimals, tolerance_bps)
+                .map(|p| (p.multiplier, p.decimals))
+        };
+        assert_eq!(rescale(p(123, 2), 4, 0), Some((12300, 4)));
+        assert_eq!(rescale(p(12300, 4), 2, 0), Some((123, 2)));
+        assert_eq!(rescale(p(12345, 4), 2, 0), None);
+        // 45 is 0.36% of 12345.
+        assert_eq!(rescale(p(12345, 4), 2, 36), None);
+        assert_eq!(rescale(p(12345, 4), 2, 37), Some((123, 2)));
+        assert_eq!(rescale(p(u128::MAX, 0), 1, 0), None);
+        assert_eq!(rescale(p(0, 0), 77, 0), Some((0, 77)));
+        assert_eq!(rescale(p(1, 77), 0, MAX_BPS), Some((0, 0)));
+    }
+
+    #[test]
+    fn test_price_deviation_bps() {
+        let p = |multiplier, decimals| Price {
+            multiplier,
+            decimals,
+        };
+        assert_eq!(p(9950, 4).deviation_bps(&p(1, 0)), Some(50));
+        assert_eq!(p(100500, 5).deviation_bps(&p(10000, 4)), Some(50));
+        assert_eq!(p(1, 0).deviation_bps(&p(1, 0)), Some(0));
+        assert_eq!(p(u128::MAX, 0).deviation_bps(&p(1, 0)), Some(u32::MAX));
+        assert_eq!(p(1, 0).deviation_bps(&p(0, 0)), None);
+        // `diff * MAX_BPS` overflows.
+        let target = p(10u128.pow(37), 77);
+        assert_eq!(
+            p(101 * 10u128.pow(35), 77).deviation_bps(&target),
+            Some(100)
+        );
+        assert_eq!(p(99 * 10u128.pow(35), 77).deviation_bps(&target), Some(100));
+        assert_eq!(
+            p(3 * 10u128.pow(37), 77).deviation_bps(&target),
+            Some(20000)
+        );
+        // `diff % target * MAX_BPS` overflows.
+        let target = p(3 * 10u128.pow(38), 77);
+        let price = |diff| p(3 * 10u128.pow(38) - diff, 77);
+        assert_eq!(price(3 * 10u128.pow(34)).deviation_bps(&target), Some(1));
+        assert_eq!(
+            price(3 * 10u128.pow(34) - 1).deviation_bps(&target),
+            Some(0)
+        );
+        assert_eq!(price(10u128.pow(38) - 1).deviation_bps(&target), Some(3333));
+    }
+
+    /// Compares the exact values `multiplier / 10**decimals` using decimal strings aligned to the
+    /// same number of decimals.
+    fn exact_cmp(a: &Price, b: &Price) -> Ordering {
+        let decimals = std::cmp::max(a.decimals, b.decimals);
+        let digits = |p: &Price| {
+            if p.multiplier == 0 {
+                "0".to_string()
+            } else {
+                p.multiplier.to_string() + &"0".repeat((decimals - p.decimals) as usize)
+            }
+        };
+        let (a, b) = (digits(a), digits(b));
+        a.len().cmp(&b.len()).then_with(|| a.cmp(&b))
+    }
+
+    proptest! {
+        #[test]
+        fn prop_fraction_bps(a in any::<u64>(), b in 1..=u64::MAX) {
+            let (a, b) = (std::cmp::min(a, b - 1) as u128, b as u128);
+            prop_assert_eq!(fraction_bps(a, b), a * MAX_BPS as u128 / b);
+        }
+
+        #[test]
+        fn prop_price_cmp_antisymmetric(a in price(0..=u128::MAX), b in price(0..=u128::MAX)) {
+            prop_assert_eq!(a.cmp(&b), b.cmp(&a).reverse());
+            prop_assert_eq!(a == b, a.cmp(&b) == Ordering::Equal);
+        }
+
+        #[test]
+        fn prop_price_cmp_transitive(
+            a in price(0..=u128::MAX),
+            b in price(0..=u128::MAX),
+            c in price(0..=u128::MAX),
+        ) {
+            let mut prices = [a, b, c];
+            prices.sort();
+            prop_assert!(prices[0] <= prices[2]);
+            prop_assert!(exact_cmp(&prices[0], &prices[1]) != Ordering::Greater);
+            prop_assert!(exact_cmp(&prices[1], &prices[2]) != Ordering::Greater);
+        }
+
+        #[test]
+        fn prop_price_cmp_exact(a in price(0..=u128::MAX), b in price(0..=u128::MAX)) {
+            prop_assert_eq!(a.cmp(&b), exact_cmp(&a, &b));
+        }
+
+        #[test]
+        fn prop_price_cmp_exact_small_multipliers(a in price(0..1000u128), b in price(0..1000u128)) {
+            prop_assert_eq!(a.cmp(&b), exact_cmp(&a, &b));
+        }
+
+        #[test]
+        fn prop_price_cmp_exact_zero(a in price(0..=1u128), b in price(0..=u128::MAX)) {
+            prop_assert_eq!(a.cmp(&b), exact_cmp(&a, &b));
+            prop_assert_eq!(b.cmp(&a), exact_cmp(&b, &a));
+        }
+
+        #[test]
+        fn prop_price_cmp_rescaled(a in price(1..=u128::MAX / 10)) {
+            prop_assume!(a.decimals < MAX_VALID_DECIMALS);
+            let rescaled = Price {
+                multiplier: a.multiplier * 10,
+                decimals: a.decimals + 1,
+            };
+            prop_assert_eq!(a.cmp(&rescaled), Ordering::Equal);
+        }
+
+        #[test]
+        fn prop_price_rescale(a in price(0..=u128::MAX), decimals in 0..=MAX_VALID_DECIMALS) {
+            if let Some(b) = a.rescale(decimals, 0) {
+                prop_assert_eq!(b.decimals, decimals);
+                prop_assert_eq!(a.cmp(&b), Ordering::Equal);
+            }
+            if let Some(b) = a.rescale(decimals, MAX_BPS) {
+                prop_assert!(b <= a);
+            }
+        }
+
+        #[test]
+        fn prop_u128_dec_format_round_trip(a in price(0..=u128::MAX)) {
+            let json = serde_json::to_string(&a).unwrap();
+            let b: Price = serde_json::from_str(&json).unwrap();
+            prop_assert_eq!(a.multiplier, b.multiplier);
+            prop_assert_eq!(a.decimals, b.decimals);
+        }
+
+        #[test]
+        fn prop_u128_dec_format_parse(s in "\\PC*|[+-]?[0-9]{0,45}") {
+            let res = serde_json::from_value::<Price>(json!({
+                "multiplier": s,
+                "decimals": 0,
+            }));
+            match s.parse::<u128>() {
+                Ok(multiplier) => prop_assert_eq!(res.unwrap().multiplier, multiplier),
+                Err(_) => prop_assert!(res.is_err()),
+            }
+        }
+
+        #[test]
+        fn prop_u64_dec_format_round_trip(n in any::<u64>()) {
+            let json = serde_json::to_string(&U64Dec(n)).unwrap();
+            prop_assert_eq!(&json, &format!("\"{}\"", n));
+            prop_assert_eq!(serde_json::from_str::<U64Dec>(&json).unwrap(), U64Dec(n));
+        }
+
+        #[test]
+        fn prop_u64_dec_format_parse(s in "\\PC*|[+-]?[0-9]{0,25}") {
+            let res = serde_json::from_value::<U64Dec>(json!(s));
+            match s.parse::<u64>() {
+                Ok(n) => prop_assert_eq!(res.unwrap(), U64Dec(n)),
+                Err(_) => prop_assert!(res.is_err()),
+            }
+        }
+
+        /// Only JSON strings are accepted, numbers lose precision in JS.
+        #[test]
+        fn prop_dec_format_rejects_numbers(n in any::<u64>()) {
+            prop_assert!(serde_json::from_value::<U64Dec>(json!(n)).is_err());
+            let price = json!({
+                "multiplier": n,
+                "decimals": 0,
+            });
+            prop_assert!(serde_json::from_value::<Price>(price).is_err());
+        }
+    }
+}
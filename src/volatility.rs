@@ -0,0 +1,137 @@
+use crate::*;
+
+/// Decimals of the volatility returned by price views, e.g. `wrap.near%86400`.
+pub const VOLATILITY_DECIMALS: u8 = 12;
+
+/// Realized volatility of the asset median over a rolling window. Squared log returns of median
+/// changes are summed with an exponential decay of `period_sec`, so the sum estimates the variance
+/// of returns over the window.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AssetVolatility {
+    pub period_sec: DurationSec,
+    /// The time of the first median. The volatility is not available before a full window.
+    #[serde(with = "u64_dec_format")]
+    pub started_at: Timestamp,
+    /// The time of the last median change.
+    #[serde(with = "u64_dec_format")]
+    pub timestamp: Timestamp,
+    /// The decayed sum of squared log returns at `timestamp`.
+    pub variance: f64,
+}
+
+impl AssetVolatility {
+    pub fn new(period_sec: DurationSec) -> Self {
+        Self {
+            period_sec,
+            started_at: 0,
+            timestamp: 0,
+            variance: 0.0,
+        }
+    }
+
+    fn decay(&self, timestamp: Timestamp) -> f64 {
+        let time_diff = timestamp.saturating_sub(self.timestamp);
+        (-(time_diff as f64) / to_nano(self.period_sec) as f64).exp()
+    }
+
+    /// Records the change of the median from `previous` to `median`.
+    pub fn record(&mut self, previous: Option<Price>, median: Option<Price>, timestamp: Timestamp) {
+        if median.is_some() && self.started_at == 0 {
+            self.started_at = timestamp;
+            self.timestamp = timestamp;
+        }
+        if let Some(log_return) = previous.zip(median).and_then(|(p, m)| log_return(&p, &m)) {
+            self.variance = self.variance * self.decay(timestamp) + log_return * log_return;
+            self.timestamp = timestamp;
+        }
+    }
+
+    /// Returns the volatility over the window at the given time, or `None` during the first
+    /// window.
+    pub fn volatility(&self, timestamp: Timestamp) -> Option<f64> {
+        if self.started_at == 0 || timestamp < self.started_at + to_nano(self.period_sec) {
+            return None;
+        }
+        Some((self.variance * self.decay(timestamp)).sqrt())
+    }
+}
+
+/// `ln(price / previous)`, or `None` if either of the prices is zero.
+fn log_return(previous: &Price, price: &Price) -> Option<f64> {
+    if previous.multiplier == 0 || price.multiplier == 0 {
+        return None;
+    }
+    let ratio = price.multiplier as f64 / previous.multiplier as f64
+        * 10f64.powi(previous.decimals as i32 - price.decimals as i32);
+    Some(ratio.ln())
+}
+
+impl Asset {
+    /// Returns the volatility as a price with `VOLATILITY_DECIMALS`, e.g. `0.05` is 5% over the
+    /// window. Requires the median to be recent.
+    pub fn volatility_price(
+        &self,
+        period_sec: DurationSec,
+        timestamp_cut: Timestamp,
+    ) -> Option<Price> {
+        if self.last_median.is_none() || self.last_median_timestamp < timestamp_cut {
+            return None;
+        }
+        let volatility = self
+            .volatilities
+            .iter()
+            .find(|v| v.period_sec == period_sec)?
+            .volatility(self.last_median_timestamp)?;
+        Some(Price {
+            multiplier: (volatility * 10f64.powi(VOLATILITY_DECIMALS as i32)).round() as u128,
+            decimals: VOLATILITY_DECIMALS,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    fn p(multiplier: u128) -> Option<Price> {
+        Some(Price {
+            multiplier,
+            decimals: 4,
+        })
+    }
+
+    #[test]
+    fn test_volatility_warm_up() {
+        let mut v = AssetVolatility::new(100);
+        v.record(None, p(10000), to_nano(1));
+        assert!(v.volatility(to_nano(100)).is_none());
+        assert_eq!(v.volatility(to_nano(101)), Some(0.0));
+    }
+
+    #[test]
+    fn test_volatility() {
+        let mut v = AssetVolatility::new(100);
+        v.record(None, p(10000), to_nano(1));
+        v.record(p(10000), p(11000), to_nano(101));
+        assert_relative_eq!(
+            v.volatility(to_nano(101)).unwrap(),
+            1.1f64.ln(),
+            max_relative = 1e-12
+        );
+        v.record(p(11000), p(10000), to_nano(201));
+        let variance = 1.1f64.ln().powi(2) * ((-1.0f64).exp() + 1.0);
+        assert_relative_eq!(
+            v.volatility(to_nano(201)).unwrap(),
+            variance.sqrt(),
+            max_relative = 1e-12
+        );
+        // Decays without changes.
+        assert_relative_eq!(
+            v.volatility(to_nano(401)).unwrap(),
+            (variance * (-2.0f64).exp()).sqrt(),
+            max_relative = 1e-12
+        );
+    }
+}
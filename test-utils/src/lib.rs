@@ -0,0 +1,310 @@
+//! Sim-test harness for the price oracle. Downstream protocols can deploy the real oracle wasm
+//! with [`Env::setup`], register oracles and assets, make reports and receive prices through
+//! `oracle_call` with the mock receiver from the `mock-receiver` crate.
+
+use near_sdk::json_types::U128;
+use near_sdk::serde::Deserialize;
+use near_sdk::serde_json::{self, json};
+use near_sdk::{AccountId, Balance, Gas, Timestamp};
+use near_sdk_sim::runtime::GenesisConfig;
+use near_sdk_sim::{init_simulator, to_yocto, ExecutionResult, UserAccount};
+use price_oracle::{
+    AssetCommitment, AssetId, AssetPrice, AssetReveal, DurationSec, MigrationStatus, Oracle, Price,
+    PriceData,
+};
+
+pub const DEFAULT_GAS: Gas = Gas(Gas::ONE_TERA.0 * 15);
+pub const MAX_GAS: Gas = Gas(Gas::ONE_TERA.0 * 300);
+
+pub const NEAR: &str = "near";
+pub const ORACLE_ID: &str = "oracle.near";
+pub const OWNER_ID: &str = "owner.near";
+pub const RECEIVER_ID: &str = "receiver.near";
+
+pub const WRAP_NEAR: &str = "wrap.near";
+
+pub fn to_nano(timestamp: u32) -> Timestamp {
+    Timestamp::from(timestamp) * 10u64.pow(9)
+}
+pub fn ts(sec: u32) -> Timestamp {
+    to_nano(1_600_000_000 + sec)
+}
+pub fn a(account_id: &str) -> AccountId {
+    AccountId::new_unchecked(account_id.to_string())
+}
+
+/// A call recorded by the mock receiver.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReceivedCall {
+    pub sender_id: AccountId,
+    pub data: PriceData,
+    pub msg: String,
+}
+
+pub struct Env {
+    pub root: UserAccount,
+    pub near: UserAccount,
+    pub owner: UserAccount,
+    pub contract: UserAccount,
+    pub users: Vec<UserAccount>,
+    /// Deposit attached to owner calls that add storage. Versions before 0.7.0 require 1 yocto.
+    pub storage_deposit: Balance,
+}
+
+impl Env {
+    pub fn setup(wasm_binary: &[u8]) -> Self {
+        let mut genesis_config = GenesisConfig::default();
+        genesis_config.genesis_time = ts(0);
+        genesis_config.block_prod_time = 0;
+
+        let root = init_simulator(Some(genesis_config));
+        let near = root.create_user(a(NEAR), to_yocto("1000000"));
+        let owner = near.create_user(a(OWNER_ID), to_yocto("10000"));
+
+        let contract = near.deploy_and_init(
+            wasm_binary,
+            a(ORACLE_ID),
+            "new",
+            &json!({
+                "recency_duration_sec": 90u32,
+                "owner_id": OWNER_ID,
+                "near_claim_amount": U128(to_yocto("5")),
+            })
+            .to_string()
+            .into_bytes(),
+            to_yocto("1000"),
+            DEFAULT_GAS.0,
+        );
+
+        let users = (0..5)
+            .map(|i| near.create_user(a(format!("user_{}.near", i).as_str()), to_yocto("100")))
+            .collect();
+
+        Self {
+            root,
+            near,
+            owner,
+            contract,
+            users,
+            storage_deposit: to_yocto("1"),
+        }
+    }
+
+    pub fn skip_time(&self, seconds: u32) {
+        self.near.borrow_runtime_mut().cur_block.block_timestamp += to_nano(seconds);
+    }
+
+    pub fn add_oracle(&self, user: &UserAccount) {
+        self.owner
+            .call(
+                self.contract.account_id(),
+                "add_oracle",
+                &json!({
+                    "account_id": user.account_id(),
+                })
+                .to_string()
+                .into_bytes(),
+                DEFAULT_GAS.0,
+                self.storage_deposit,
+            )
+            .assert_success();
+    }
+
+    pub fn add_asset(&self, asset_id: &str) {
+        self.owner
+            .call(
+                self.contract.account_id(),
+                "add_asset",
+                &json!({
+                    "asset_id": asset_id,
+                })
+                .to_string()
+                .into_bytes(),
+                DEFAULT_GAS.0,
+                self.storage_deposit,
+            )
+            .assert_success();
+    }
+
+    pub fn add_asset_ema(&self, asset_id: &str, period_sec: DurationSec) {
+        self.owner
+            .call(
+                self.contract.account_id(),
+                "add_asset_ema",
+                &json!({
+                    "asset_id": asset_id,
+                    "period_sec": period_sec,
+                })
+                .to_string()
+                .into_bytes(),
+                DEFAULT_GAS.0,
+                self.storage_deposit,
+            )
+            .assert_success();
+    }
+
+    pub fn owner_call(
+        &self,
+        method_name: &str,
+        args: serde_json::Value,
+        deposit: Balance,
+    ) -> ExecutionResult {
+        self.owner.call(
+            self.contract.account_id(),
+            method_name,
+            &args.to_string().into_bytes(),
+            DEFAULT_GAS.0,
+            deposit,
+        )
+    }
+
+    pub fn get_migration_status(&self) -> MigrationStatus {
+        self.near
+            .view(self.contract.account_id(), "get_migration_status", &[])
+            .unwrap_json()
+    }
+
+    pub fn report_prices(&self, user: &UserAccount, prices: Vec<AssetPrice>) -> ExecutionResult {
+        user.call(
+            self.contract.account_id(),
+            "report_prices",
+            &json!({
+                "prices": prices,
+            })
+            .to_string()
+            .into_bytes(),
+            MAX_GAS.0,
+            0,
+        )
+    }
+
+    pub fn confirm_prices(&self, user: &UserAccount, asset_ids: Vec<AssetId>) -> ExecutionResult {
+        user.call(
+            self.contract.account_id(),
+            "confirm_prices",
+            &json!({
+                "asset_ids": asset_ids,
+            })
+            .to_string()
+            .into_bytes(),
+            MAX_GAS.0,
+            0,
+        )
+    }
+
+    pub fn commit_prices(
+        &self,
+        user: &UserAccount,
+        commitments: Vec<AssetCommitment>,
+    ) -> ExecutionResult {
+        user.call(
+            self.contract.account_id(),
+            "commit_prices",
+            &json!({
+                "commitments": commitments,
+            })
+            .to_string()
+            .into_bytes(),
+            MAX_GAS.0,
+            0,
+        )
+    }
+
+    pub fn reveal_prices(&self, user: &UserAccount, prices: Vec<AssetReveal>) -> ExecutionResult {
+        user.call(
+            self.contract.account_id(),
+            "reveal_prices",
+            &json!({
+                "prices": prices,
+            })
+            .to_string()
+            .into_bytes(),
+            MAX_GAS.0,
+            0,
+        )
+    }
+
+    pub fn get_oracle(&self, account_id: &AccountId) -> Oracle {
+        self.near
+            .view(
+                self.contract.account_id(),
+                "get_oracle",
+                &json!({
+                    "account_id": account_id,
+                })
+                .to_string()
+                .into_bytes(),
+            )
+            .unwrap_json()
+    }
+
+    pub fn get_price_data(&self, asset_ids: Option<Vec<AssetId>>) -> PriceData {
+        self.near
+            .view(
+                self.contract.account_id(),
+                "get_price_data",
+                &json!({
+                    "asset_ids": asset_ids,
+                })
+                .to_string()
+                .into_bytes(),
+            )
+            .unwrap_json()
+    }
+
+    /// Deploys the mock `oracle_on_call` receiver as `receiver.near`.
+    pub fn deploy_receiver(&self, wasm_binary: &[u8]) -> UserAccount {
+        self.near
+            .deploy(wasm_binary, a(RECEIVER_ID), to_yocto("100"))
+    }
+
+    pub fn oracle_call(
+        &self,
+        user: &UserAccount,
+        receiver_id: &AccountId,
+        asset_ids: Option<Vec<AssetId>>,
+        msg: &str,
+    ) -> ExecutionResult {
+        user.call(
+            self.contract.account_id(),
+            "oracle_call",
+            &json!({
+                "receiver_id": receiver_id,
+                "asset_ids": asset_ids,
+                "msg": msg,
+            })
+            .to_string()
+            .into_bytes(),
+            MAX_GAS.0,
+            1,
+        )
+    }
+
+    /// Returns calls recorded by the mock receiver.
+    pub fn get_received_calls(&self, receiver: &UserAccount) -> Vec<ReceivedCall> {
+        self.near
+            .view(receiver.account_id(), "get_calls", &[])
+            .unwrap_json()
+    }
+
+    /// Reports `WRAP_NEAR` prices with 28 decimals from the first users. Zero multipliers are
+    /// skipped.
+    pub fn make_reports(&self, multipliers: &[u128]) {
+        for (i, &multiplier) in multipliers.iter().enumerate() {
+            if multiplier > 0 {
+                self.report_prices(
+                    &self.users[i],
+                    vec![AssetPrice {
+                        asset_id: WRAP_NEAR.to_string(),
+                        price: Price {
+                            multiplier,
+                            decimals: 28,
+                        },
+                    }],
+                )
+                .assert_success();
+            }
+        }
+    }
+}
@@ -1,187 +1,33 @@
-use near_sdk::json_types::U128;
+use near_sdk::json_types::Base58CryptoHash;
 use near_sdk::serde_json::json;
-use near_sdk::{AccountId, Gas, Timestamp};
-use near_sdk_sim::runtime::GenesisConfig;
-use near_sdk_sim::{init_simulator, to_yocto, ExecutionResult, UserAccount};
-use price_oracle::{AssetId, AssetPrice, DurationSec, Price, PriceData};
+use near_sdk::{AccountId, Balance, Gas};
+use near_sdk_sim::{to_yocto, UserAccount};
+use price_oracle::{
+    Asset, AssetCommitment, AssetId, AssetPrice, AssetReveal, DurationSec, MigrationStatus,
+    PegStatus, PendingOwner, PreviousCode, Price, PriceData, Proposal, Report, ReportStatus, Role,
+    StagedUpgrade, StorageBalance,
+};
+use price_oracle_test_utils::*;
 
 near_sdk_sim::lazy_static_include::lazy_static_include_bytes! {
     CONTARCT_WASM_BYTES => "res/price_oracle.wasm",
-    CONTRACT_0_5_0_WASM_BYTES => "res/price_oracle_0.5.0.wasm",
+    MOCK_RECEIVER_WASM_BYTES => "res/mock_receiver.wasm",
+    CONTRACT_0_6_0_WASM_BYTES => "res/price_oracle_0.6.0.wasm",
 }
 
-const PREVIOUS_VERSION: &'static str = "0.5.0";
-const LATEST_VERSION: &'static str = "0.6.0";
+const PREVIOUS_VERSION: &'static str = "0.6.0";
+const LATEST_VERSION: &'static str = "0.7.0";
 
-pub const DEFAULT_GAS: Gas = Gas(Gas::ONE_TERA.0 * 15);
-pub const MAX_GAS: Gas = Gas(Gas::ONE_TERA.0 * 300);
-
-pub const NEAR: &str = "near";
-pub const ORACLE_ID: &str = "oracle.near";
-pub const OWNER_ID: &str = "owner.near";
-
-pub const WRAP_NEAR: &str = "wrap.near";
 pub const WRAP_NEAR_3600: &str = "wrap.near#3600";
-
-pub fn to_nano(timestamp: u32) -> Timestamp {
-    Timestamp::from(timestamp) * 10u64.pow(9)
-}
-pub fn ts(sec: u32) -> Timestamp {
-    to_nano(1_600_000_000 + sec)
-}
-pub fn a(account_id: &str) -> AccountId {
-    AccountId::new_unchecked(account_id.to_string())
-}
-
-pub struct Env {
-    pub root: UserAccount,
-    pub near: UserAccount,
-    pub owner: UserAccount,
-    pub contract: UserAccount,
-    pub users: Vec<UserAccount>,
-}
-
-impl Env {
-    pub fn setup(wasm_binary: &[u8]) -> Self {
-        let mut genesis_config = GenesisConfig::default();
-        genesis_config.genesis_time = ts(0);
-        genesis_config.block_prod_time = 0;
-
-        let root = init_simulator(Some(genesis_config));
-        let near = root.create_user(a(NEAR), to_yocto("1000000"));
-        let owner = near.create_user(a(OWNER_ID), to_yocto("10000"));
-
-        let contract = near.deploy_and_init(
-            wasm_binary,
-            a(ORACLE_ID),
-            "new",
-            &json!({
-                "recency_duration_sec": 90u32,
-                "owner_id": OWNER_ID,
-                "near_claim_amount": U128(to_yocto("5")),
-            })
-            .to_string()
-            .into_bytes(),
-            to_yocto("1000"),
-            DEFAULT_GAS.0,
-        );
-
-        let users = (0..5)
-            .map(|i| near.create_user(a(format!("user_{}.near", i).as_str()), to_yocto("100")))
-            .collect();
-
-        Self {
-            root,
-            near,
-            owner,
-            contract,
-            users,
-        }
-    }
-
-    pub fn skip_time(&self, seconds: u32) {
-        self.near.borrow_runtime_mut().cur_block.block_timestamp += to_nano(seconds);
-    }
-
-    pub fn add_oracle(&self, user: &UserAccount) {
-        self.owner
-            .call(
-                self.contract.account_id(),
-                "add_oracle",
-                &json!({
-                    "account_id": user.account_id(),
-                })
-                .to_string()
-                .into_bytes(),
-                DEFAULT_GAS.0,
-                1,
-            )
-            .assert_success();
-    }
-
-    pub fn add_asset(&self, asset_id: &str) {
-        self.owner
-            .call(
-                self.contract.account_id(),
-                "add_asset",
-                &json!({
-                    "asset_id": asset_id,
-                })
-                .to_string()
-                .into_bytes(),
-                DEFAULT_GAS.0,
-                1,
-            )
-            .assert_success();
-    }
-
-    pub fn add_asset_ema(&self, asset_id: &str, period_sec: DurationSec) {
-        self.owner
-            .call(
-                self.contract.account_id(),
-                "add_asset_ema",
-                &json!({
-                    "asset_id": asset_id,
-                    "period_sec": period_sec,
-                })
-                .to_string()
-                .into_bytes(),
-                DEFAULT_GAS.0,
-                1,
-            )
-            .assert_success();
-    }
-
-    pub fn report_prices(&self, user: &UserAccount, prices: Vec<AssetPrice>) -> ExecutionResult {
-        user.call(
-            self.contract.account_id(),
-            "report_prices",
-            &json!({
-                "prices": prices,
-            })
-            .to_string()
-            .into_bytes(),
-            MAX_GAS.0,
-            0,
-        )
-    }
-
-    pub fn get_price_data(&self, asset_ids: Option<Vec<AssetId>>) -> PriceData {
-        self.near
-            .view(
-                self.contract.account_id(),
-                "get_price_data",
-                &json!({
-                    "asset_ids": asset_ids,
-                })
-                .to_string()
-                .into_bytes(),
-            )
-            .unwrap_json()
-    }
-
-    pub fn make_reports(&self, multipliers: &[u128]) {
-        for (i, &multiplier) in multipliers.iter().enumerate() {
-            if multiplier > 0 {
-                self.report_prices(
-                    &self.users[i],
-                    vec![AssetPrice {
-                        asset_id: WRAP_NEAR.to_string(),
-                        price: Price {
-                            multiplier,
-                            decimals: 28,
-                        },
-                    }],
-                )
-                .assert_success();
-            }
-        }
-    }
-}
+pub const DAI: &str = "dai.bridge.near";
 
 #[test]
 pub fn test_init() {
-    Env::setup(&CONTARCT_WASM_BYTES);
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    let status = e.get_migration_status();
+    assert_eq!(status.schema_version, 1);
+    assert!(!status.in_progress);
 }
 
 #[test]
@@ -474,6 +320,14 @@ pub fn test_ema() {
         })
     );
 
+    // A malformed period has no price.
+    let price_data = e.get_price_data(Some(vec![
+        format!("{}#abc", WRAP_NEAR),
+        WRAP_NEAR_3600.to_string(),
+    ]));
+    assert!(price_data.prices[0].price.is_none());
+    assert!(price_data.prices[1].price.is_some());
+
     // In another minute, the EMA price becomes unavailable.
     e.skip_time(60);
 
@@ -489,8 +343,37 @@ pub fn test_ema() {
 }
 
 #[test]
-pub fn test_update() {
-    let e = Env::setup(&CONTRACT_0_5_0_WASM_BYTES);
+pub fn test_oracle_call() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+    e.add_asset(WRAP_NEAR);
+    e.make_reports(&[100000]);
+
+    let receiver = e.deploy_receiver(&MOCK_RECEIVER_WASM_BYTES);
+    e.oracle_call(&e.users[1], &receiver.account_id(), None, "hello")
+        .assert_success();
+
+    let calls = e.get_received_calls(&receiver);
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].sender_id, e.users[1].account_id());
+    assert_eq!(calls[0].msg, "hello");
+    assert_eq!(
+        &calls[0].data.prices[0].price,
+        &Some(Price {
+            multiplier: 100000,
+            decimals: 28
+        })
+    );
+
+    // Failed calls are not recorded.
+    e.oracle_call(&e.users[1], &receiver.account_id(), None, "fail");
+    assert_eq!(e.get_received_calls(&receiver).len(), 1);
+}
+
+#[test]
+pub fn test_consumer_recency() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
 
     e.add_oracle(&e.users[0]);
     e.add_oracle(&e.users[1]);
@@ -500,35 +383,2320 @@ pub fn test_update() {
 
     e.make_reports(&[100000, 110000, 106000]);
 
-    let price_data = e.get_price_data(None);
-    assert_eq!(price_data.prices.len(), 1);
+    e.skip_time(60);
 
-    let version: String = e
+    let get_price_data = |recency_duration_sec: Option<DurationSec>,
+                          min_num_recent_reports: Option<u32>|
+     -> PriceData {
+        e.near
+            .view(
+                e.contract.account_id(),
+                "get_price_data",
+                &json!({
+                    "recency_duration_sec": recency_duration_sec,
+                    "min_num_recent_reports": min_num_recent_reports,
+                })
+                .to_string()
+                .into_bytes(),
+            )
+            .unwrap_json()
+    };
+
+    // The contract config is used by default.
+    let price_data = get_price_data(None, None);
+    assert_eq!(price_data.recency_duration_sec, 90);
+    assert!(price_data.prices[0].price.is_some());
+
+    // A consumer can require fresher reports.
+    let price_data = get_price_data(Some(30), None);
+    assert_eq!(price_data.recency_duration_sec, 30);
+    assert!(price_data.prices[0].price.is_none());
+
+    // But can't relax the contract config.
+    let price_data = get_price_data(Some(3600), None);
+    assert_eq!(price_data.recency_duration_sec, 90);
+    assert!(price_data.prices[0].price.is_some());
+
+    // A consumer can require more reports.
+    let price_data = get_price_data(None, Some(3));
+    assert!(price_data.prices[0].price.is_some());
+    let price_data = get_price_data(None, Some(4));
+    assert!(price_data.prices[0].price.is_none());
+
+    // But can't require fewer than the contract config.
+    e.skip_time(60);
+    e.make_reports(&[100000]);
+    let price_data = get_price_data(None, Some(1));
+    assert!(price_data.prices[0].price.is_none());
+}
+
+#[test]
+pub fn test_consumer_recency_oracle_call() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+    e.add_oracle(&e.users[1]);
+    e.add_oracle(&e.users[2]);
+
+    e.add_asset(WRAP_NEAR);
+
+    e.make_reports(&[100000, 110000, 106000]);
+
+    e.skip_time(60);
+
+    let receiver = e.deploy_receiver(&MOCK_RECEIVER_WASM_BYTES);
+    let oracle_call = |recency_duration_sec: Option<DurationSec>,
+                       min_num_recent_reports: Option<u32>|
+     -> PriceData {
+        e.users[3]
+            .call(
+                e.contract.account_id(),
+                "oracle_call",
+                &json!({
+                    "receiver_id": receiver.account_id(),
+                    "msg": "",
+                    "recency_duration_sec": recency_duration_sec,
+                    "min_num_recent_reports": min_num_recent_reports,
+                })
+                .to_string()
+                .into_bytes(),
+                MAX_GAS.0,
+                1,
+            )
+            .assert_success();
+        e.get_received_calls(&receiver).pop().unwrap().data
+    };
+
+    let price_data = oracle_call(None, None);
+    assert_eq!(price_data.recency_duration_sec, 90);
+    assert!(price_data.prices[0].price.is_some());
+
+    // The receiver gets the data under the tightened recency.
+    let price_data = oracle_call(Some(30), None);
+    assert_eq!(price_data.recency_duration_sec, 30);
+    assert!(price_data.prices[0].price.is_none());
+
+    // And under the increased number of reports.
+    let price_data = oracle_call(None, Some(3));
+    assert!(price_data.prices[0].price.is_some());
+    let price_data = oracle_call(None, Some(4));
+    assert!(price_data.prices[0].price.is_none());
+
+    assert_eq!(e.get_received_calls(&receiver).len(), 4);
+}
+
+#[test]
+pub fn test_quote_asset() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+
+    e.add_asset(WRAP_NEAR);
+    e.add_asset(DAI);
+
+    e.make_reports(&[100000]);
+
+    let get_price_data = |quote_asset_id: &str| -> PriceData {
+        e.near
+            .view(
+                e.contract.account_id(),
+                "get_price_data",
+                &json!({
+                    "asset_ids": vec![WRAP_NEAR, WRAP_NEAR_3600],
+                    "quote_asset_id": quote_asset_id,
+                })
+                .to_string()
+                .into_bytes(),
+            )
+            .unwrap_json()
+    };
+
+    // No DAI price yet, so nothing can be quoted in DAI.
+    let price_data = get_price_data(DAI);
+    assert_eq!(price_data.quote_asset_id, Some(DAI.to_string()));
+    assert!(price_data.prices[0].price.is_none());
+
+    e.report_prices(
+        &e.users[0],
+        vec![AssetPrice {
+            asset_id: DAI.to_string(),
+            price: Price {
+                multiplier: 101,
+                decimals: 20,
+            },
+        }],
+    )
+    .assert_success();
+
+    let price_data = get_price_data(DAI);
+    let price = price_data.prices[0].price.unwrap();
+    // 1 NEAR = 10 USD, 1 DAI = 1.01 USD, so 1 NEAR ~= 9.90099 DAI.
+    let near_in_dai = price.multiplier as f64 / 10f64.powi(price.decimals as i32 - 24 + 18);
+    assert!((near_in_dai - 9.90099).abs() < 1e-5);
+    assert_eq!(
+        Price {
+            multiplier: 100000,
+            decimals: 28
+        }
+        .checked_div(&Price {
+            multiplier: 101,
+            decimals: 20
+        }),
+        Some(price)
+    );
+    // EMA is not configured.
+    assert!(price_data.prices[1].price.is_none());
+
+    // An asset quoted in itself is 1 unit per unit.
+    let price_data = get_price_data(WRAP_NEAR);
+    assert_eq!(
+        price_data.prices[0].price,
+        Some(Price {
+            multiplier: 1,
+            decimals: 0
+        })
+    );
+}
+
+#[test]
+pub fn test_asset_decimals() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+    e.add_oracle(&e.users[1]);
+    e.add_oracle(&e.users[2]);
+    e.add_asset(WRAP_NEAR);
+
+    let set_asset_decimals = |tolerance_bps: Option<u32>| {
+        e.owner_call(
+            "set_asset_decimals",
+            json!({
+                "asset_id": WRAP_NEAR,
+                "decimals": 24,
+                "tolerance_bps": tolerance_bps,
+            }),
+            e.storage_deposit,
+        )
+        .assert_success();
+    };
+    set_asset_decimals(None);
+
+    let report = |user: &UserAccount, multiplier: u128, decimals: u8| {
+        e.report_prices(
+            user,
+            vec![AssetPrice {
+                asset_id: WRAP_NEAR.to_string(),
+                price: Price {
+                    multiplier,
+                    decimals,
+                },
+            }],
+        )
+    };
+    report(&e.users[0], 1000000, 28).assert_success();
+    // 0.0045 is truncated, which is 0.36% of the price.
+    let res = report(&e.users[1], 12345, 26);
+    res.assert_success();
+    assert!(res.logs()[0].contains("Rejected price for wrap.near: can't be rescaled"));
+    report(&e.users[2], 110, 24).assert_success();
+
+    let price = e.get_price_data(None).prices[0].price.unwrap();
+    assert_eq!((price.multiplier, price.decimals), (110, 24));
+
+    let reports: Vec<Report> = e
         .near
-        .view(e.contract.account_id(), "get_version", &[])
+        .view(
+            e.contract.account_id(),
+            "get_asset_reports",
+            &json!({
+                "asset_id": WRAP_NEAR,
+            })
+            .to_string()
+            .into_bytes(),
+        )
         .unwrap_json();
+    assert_eq!(reports.len(), 2);
+    assert!(reports.iter().all(|report| report.price.decimals == 24));
 
-    assert_eq!(version, PREVIOUS_VERSION);
+    set_asset_decimals(Some(50));
+    report(&e.users[1], 12345, 26).assert_success();
+    let reports: Vec<Report> = e
+        .near
+        .view(
+            e.contract.account_id(),
+            "get_asset_reports",
+            &json!({
+                "asset_id": WRAP_NEAR,
+            })
+            .to_string()
+            .into_bytes(),
+        )
+        .unwrap_json();
+    assert_eq!(reports.len(), 3);
+    let price = e.get_price_data(None).prices[0].price.unwrap();
+    assert_eq!((price.multiplier, price.decimals), (110, 24));
+}
 
-    e.owner
-        .create_transaction(a(ORACLE_ID))
-        .function_call(
-            "upgrade".to_string(),
-            CONTARCT_WASM_BYTES.to_vec(),
-            MAX_GAS.0,
-            0,
+#[test]
+pub fn test_price_bounds() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+    e.add_asset(WRAP_NEAR);
+
+    let p = |multiplier: u128| Price {
+        multiplier,
+        decimals: 28,
+    };
+    let set_bounds = |min_price: Price, max_price: Price| {
+        e.owner_call(
+            "set_asset_price_bounds",
+            json!({
+                "asset_id": WRAP_NEAR,
+                "min_price": min_price,
+                "max_price": max_price,
+                "max_change_bps": 1000,
+            }),
+            e.storage_deposit,
         )
-        .submit();
+    };
+    assert!(!set_bounds(p(200000), p(50000)).is_ok());
+    set_bounds(p(50000), p(200000)).assert_success();
+
+    let report = |multiplier: u128| {
+        e.report_prices(
+            &e.users[0],
+            vec![AssetPrice {
+                asset_id: WRAP_NEAR.to_string(),
+                price: p(multiplier),
+            }],
+        )
+    };
+    let assert_rejected = |multiplier: u128, reason: &str| {
+        let res = report(multiplier);
+        res.assert_success();
+        assert!(res.logs()[0].contains(reason), "{:?}", res.logs());
+    };
+    let get_price = || e.get_price_data(None).prices[0].price;
+
+    report(100000).assert_success();
+    assert_rejected(0, "below the min price");
+    assert_rejected(300000, "above the max price");
+    assert_rejected(115000, "changed by 1500 bps");
+    assert_eq!(get_price(), Some(p(100000)));
+    report(105000).assert_success();
+    assert_eq!(get_price(), Some(p(105000)));
+
+    // The change is not checked against a stale report.
+    e.skip_time(100);
+    report(150000).assert_success();
+    assert_eq!(get_price(), Some(p(150000)));
+
+    let oracle = e.get_oracle(&e.users[0].account_id());
+    assert_eq!(oracle.price_reports, 6);
+    assert_eq!(oracle.rejected_reports, 3);
+}
 
-    let price_data = e.get_price_data(None);
-    assert_eq!(price_data.prices.len(), 1);
+#[test]
+pub fn test_report_statuses() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
 
-    let version: String = e
-        .near
-        .view(e.contract.account_id(), "get_version", &[])
+    e.add_oracle(&e.users[0]);
+    e.add_oracle(&e.users[1]);
+    e.add_asset(WRAP_NEAR);
+    e.add_asset(DAI);
+    e.owner_call(
+        "add_asset_alias",
+        json!({
+            "alias_id": "near",
+            "asset_id": WRAP_NEAR,
+        }),
+        e.storage_deposit,
+    )
+    .assert_success();
+    e.owner_call(
+        "set_asset_oracle_whitelist",
+        json!({
+            "asset_id": DAI,
+            "oracle_ids": [e.users[1].account_id()],
+        }),
+        e.storage_deposit,
+    )
+    .assert_success();
+
+    let asset_price = |asset_id: &str, decimals: u8| AssetPrice {
+        asset_id: asset_id.to_string(),
+        price: Price {
+            multiplier: 100000,
+            decimals,
+        },
+    };
+    let statuses: Vec<ReportStatus> = e
+        .report_prices(
+            &e.users[0],
+            vec![
+                asset_price(WRAP_NEAR, 28),
+                asset_price("unknown.near", 28),
+                asset_price("near", 28),
+                asset_price(DAI, 22),
+            ],
+        )
+        .unwrap_json();
+    assert_eq!(
+        statuses,
+        vec![
+            ReportStatus::Accepted,
+            ReportStatus::UnknownAsset,
+            ReportStatus::Duplicate,
+            ReportStatus::NotWhitelisted,
+        ]
+    );
+
+    // Invalid decimals don't fail the other prices.
+    let statuses: Vec<ReportStatus> = e
+        .report_prices(
+            &e.users[1],
+            vec![asset_price(WRAP_NEAR, 78), asset_price(DAI, 22)],
+        )
         .unwrap_json();
+    assert_eq!(
+        statuses,
+        vec![ReportStatus::OutOfBounds, ReportStatus::Accepted]
+    );
 
-    assert_eq!(version, LATEST_VERSION);
+    let price_data = e.get_price_data(Some(vec![WRAP_NEAR.to_string(), DAI.to_string()]));
+    assert!(price_data.prices.iter().all(|p| p.price.is_some()));
+}
+
+#[test]
+pub fn test_confirm_prices() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+    e.add_asset(WRAP_NEAR);
+    e.add_asset(DAI);
+    e.add_asset_ema(WRAP_NEAR, 3600);
+
+    e.make_reports(&[100000]);
+    e.skip_time(60);
+    e.make_reports(&[100000]);
+
+    e.skip_time(60);
+    let statuses: Vec<ReportStatus> = e
+        .confirm_prices(
+            &e.users[0],
+            vec![
+                WRAP_NEAR.to_string(),
+                DAI.to_string(),
+                "unknown.near".to_string(),
+                WRAP_NEAR.to_string(),
+            ],
+        )
+        .unwrap_json();
+    assert_eq!(
+        statuses,
+        vec![
+            ReportStatus::Accepted,
+            ReportStatus::MissingReport,
+            ReportStatus::UnknownAsset,
+            ReportStatus::Duplicate,
+        ]
+    );
+
+    // The last report would be stale without the confirmation.
+    e.skip_time(60);
+    let price_data = e.get_price_data(Some(vec![
+        WRAP_NEAR.to_string(),
+        WRAP_NEAR_3600.to_string(),
+    ]));
+    let expected = Some(Price {
+        multiplier: 100000,
+        decimals: 28,
+    });
+    assert_eq!(price_data.prices[0].price, expected);
+    assert_eq!(price_data.prices[1].price, expected);
+
+    // Stale reports can't be confirmed.
+    e.skip_time(100);
+    let price_data = e.get_price_data(Some(vec![
+        WRAP_NEAR.to_string(),
+        WRAP_NEAR_3600.to_string(),
+    ]));
+    assert!(price_data.prices.iter().all(|p| p.price.is_none()));
+    let statuses: Vec<ReportStatus> = e
+        .confirm_prices(&e.users[0], vec![WRAP_NEAR.to_string()])
+        .unwrap_json();
+    assert_eq!(statuses, vec![ReportStatus::MissingReport]);
+
+    // Only oracles can confirm prices.
+    assert!(!e
+        .confirm_prices(&e.users[1], vec![WRAP_NEAR.to_string()])
+        .is_ok());
+}
+
+#[test]
+pub fn test_commit_reveal() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+    e.add_oracle(&e.users[1]);
+    e.add_asset(WRAP_NEAR);
+    e.owner_call(
+        "set_asset_commit_reveal",
+        json!({
+            "asset_id": WRAP_NEAR,
+            "commit_reveal": {
+                "commit_duration_sec": 60,
+                "reveal_duration_sec": 60,
+            },
+        }),
+        e.storage_deposit,
+    )
+    .assert_success();
+
+    let p = |multiplier: u128| Price {
+        multiplier,
+        decimals: 28,
+    };
+    let statuses: Vec<ReportStatus> = e
+        .report_prices(
+            &e.users[0],
+            vec![AssetPrice {
+                asset_id: WRAP_NEAR.to_string(),
+                price: p(100000),
+            }],
+        )
+        .unwrap_json();
+    assert_eq!(statuses, vec![ReportStatus::CommitRevealRequired]);
+
+    let commit = |user: &UserAccount, multiplier: u128, salt: &str| -> Vec<ReportStatus> {
+        let hash = e
+            .near
+            .view(
+                e.contract.account_id(),
+                "get_commitment_hash",
+                &json!({
+                    "oracle_id": user.account_id(),
+                    "asset_id": WRAP_NEAR,
+                    "price": p(multiplier),
+                    "salt": salt,
+                })
+                .to_string()
+                .into_bytes(),
+            )
+            .unwrap_json();
+        e.commit_prices(
+            user,
+            vec![AssetCommitment {
+                asset_id: WRAP_NEAR.to_string(),
+                hash,
+            }],
+        )
+        .unwrap_json()
+    };
+    let reveal = |user: &UserAccount, multiplier: u128, salt: &str| -> Vec<ReportStatus> {
+        e.reveal_prices(
+            user,
+            vec![AssetReveal {
+                asset_id: WRAP_NEAR.to_string(),
+                price: p(multiplier),
+                salt: salt.to_string(),
+            }],
+        )
+        .unwrap_json()
+    };
+
+    // Start of the next round.
+    let timestamp = e.get_price_data(None).timestamp;
+    e.skip_time(120 - (timestamp / to_nano(1) % 120) as u32);
+
+    assert_eq!(
+        commit(&e.users[0], 100000, "a"),
+        vec![ReportStatus::Accepted]
+    );
+    assert_eq!(
+        commit(&e.users[1], 110000, "b"),
+        vec![ReportStatus::Accepted]
+    );
+    assert_eq!(
+        reveal(&e.users[0], 100000, "a"),
+        vec![ReportStatus::WrongPhase]
+    );
+
+    e.skip_time(60);
+    assert_eq!(
+        commit(&e.users[0], 100000, "a"),
+        vec![ReportStatus::WrongPhase]
+    );
+    let price_reports = e.get_oracle(&e.users[0].account_id()).price_reports;
+    assert_eq!(
+        reveal(&e.users[0], 100000, "b"),
+        vec![ReportStatus::InvalidReveal]
+    );
+    assert_eq!(
+        reveal(&e.users[0], 100000, "a"),
+        vec![ReportStatus::Accepted]
+    );
+    assert_eq!(
+        reveal(&e.users[0], 100000, "a"),
+        vec![ReportStatus::NoCommitment]
+    );
+    assert_eq!(e.get_price_data(None).prices[0].price, Some(p(100000)));
+    // Only the accepted reveal is counted as a report.
+    assert_eq!(
+        e.get_oracle(&e.users[0].account_id()).price_reports,
+        price_reports + 1
+    );
+
+    // The unrevealed commitment is counted on the next commit of any oracle.
+    e.skip_time(60);
+    assert_eq!(
+        commit(&e.users[0], 110000, "c"),
+        vec![ReportStatus::Accepted]
+    );
+    assert_eq!(
+        e.get_oracle(&e.users[1].account_id())
+            .unrevealed_commitments,
+        1
+    );
+    assert_eq!(
+        e.get_oracle(&e.users[0].account_id())
+            .unrevealed_commitments,
+        0
+    );
+    assert_eq!(e.get_oracle(&e.users[0].account_id()).rejected_reports, 1);
+}
+
+#[test]
+pub fn test_weighted_median() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+    e.add_oracle(&e.users[1]);
+    e.add_oracle(&e.users[2]);
+    e.add_asset(WRAP_NEAR);
+    e.owner_call(
+        "set_oracle_weight",
+        json!({
+            "account_id": e.users[0].account_id(),
+            "weight": 4,
+        }),
+        1,
+    )
+    .assert_success();
+
+    let get_price = || e.get_price_data(None).prices[0].price;
+    let p = |multiplier: u128| Price {
+        multiplier,
+        decimals: 28,
+    };
+
+    e.make_reports(&[100000, 110000, 106000]);
+    assert_eq!(get_price(), Some(p(106000)));
+
+    e.owner_call(
+        "set_asset_aggregation",
+        json!({
+            "asset_id": WRAP_NEAR,
+            "aggregation": "WeightedMedian",
+        }),
+        e.storage_deposit,
+    )
+    .assert_success();
+    assert_eq!(get_price(), Some(p(100000)));
+
+    // The weight of a single oracle reaches the quorum of half of the total weight.
+    e.skip_time(100);
+    e.make_reports(&[101000]);
+    assert_eq!(get_price(), Some(p(101000)));
+
+    e.owner_call(
+        "set_asset_aggregation",
+        json!({
+            "asset_id": WRAP_NEAR,
+            "aggregation": null,
+        }),
+        e.storage_deposit,
+    )
+    .assert_success();
+    assert_eq!(get_price(), None);
+}
+
+#[test]
+pub fn test_aggregation() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    for user in &e.users {
+        e.add_oracle(user);
+    }
+    e.add_asset(WRAP_NEAR);
+
+    let set_aggregation = |aggregation: near_sdk::serde_json::Value| {
+        e.owner_call(
+            "set_asset_aggregation",
+            json!({
+                "asset_id": WRAP_NEAR,
+                "aggregation": aggregation,
+            }),
+            e.storage_deposit,
+        )
+    };
+    let get_price = || e.get_price_data(None).prices[0].price;
+    let p = |multiplier: u128| Price {
+        multiplier,
+        decimals: 28,
+    };
+
+    e.make_reports(&[10000, 10001, 10100, 10200, 13000]);
+    assert_eq!(get_price(), Some(p(10100)));
+
+    set_aggregation(json!({"TrimmedMean": {"trim_bps": 2000}})).assert_success();
+    assert_eq!(get_price(), Some(p(10100)));
+
+    set_aggregation(json!("InterquartileMean")).assert_success();
+    assert_eq!(get_price(), Some(p(10100)));
+
+    set_aggregation(json!({"TrimmedMean": {"trim_bps": 0}})).assert_success();
+    assert_eq!(get_price(), Some(p(10660)));
+
+    set_aggregation(json!({"ModeOfBuckets": {"bucket_size": p(10)}})).assert_success();
+    assert_eq!(get_price(), Some(p(10001)));
+
+    assert!(!set_aggregation(json!({"TrimmedMean": {"trim_bps": 5000}})).is_ok());
+
+    set_aggregation(near_sdk::serde_json::Value::Null).assert_success();
+    assert_eq!(get_price(), Some(p(10100)));
+}
+
+#[test]
+pub fn test_peg_status() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+    e.add_asset(DAI);
+    e.add_asset_ema(DAI, 3600);
+
+    let p = |multiplier: u128| Price {
+        multiplier,
+        decimals: 4,
+    };
+    e.owner_call(
+        "set_asset_peg",
+        json!({
+            "asset_id": DAI,
+            "peg": {
+                "target_price": p(10000),
+                "tolerance_bps": 100,
+                "ema_period_sec": 3600,
+            },
+        }),
+        e.storage_deposit,
+    )
+    .assert_success();
+
+    let report = |multiplier: u128| {
+        e.report_prices(
+            &e.users[0],
+            vec![AssetPrice {
+                asset_id: DAI.to_string(),
+                price: p(multiplier),
+            }],
+        )
+        .assert_success();
+    };
+    let get_peg_status = || -> PegStatus {
+        e.near
+            .view(
+                e.contract.account_id(),
+                "get_peg_status",
+                &json!({
+                    "asset_id": DAI,
+                })
+                .to_string()
+                .into_bytes(),
+            )
+            .unwrap_json()
+    };
+
+    report(10010);
+    let status = get_peg_status();
+    assert_eq!(status.peg_deviation_bps, Some(10));
+    assert!(!status.depegged);
+
+    // A short spike doesn't move the EMA enough.
+    e.skip_time(10);
+    report(9000);
+    let status = get_peg_status();
+    assert_eq!(status.peg_deviation_bps, Some(1000));
+    assert!(status.ema_deviation_bps.unwrap() <= 100);
+    assert!(!status.depegged);
+
+    e.skip_time(60);
+    report(9000);
+    e.skip_time(1800);
+    report(9000);
+    let status = get_peg_status();
+    assert!(status.ema_deviation_bps.unwrap() > 100);
+    assert!(status.depegged);
+    let depeg_started_at = status.depeg_started_at.unwrap();
+
+    e.skip_time(60);
+    report(8900);
+    assert_eq!(get_peg_status().depeg_started_at, Some(depeg_started_at));
+
+    report(10000);
+    let status = get_peg_status();
+    assert!(!status.depegged);
+    assert!(status.depeg_started_at.is_none());
+}
+
+#[test]
+pub fn test_volatility() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+    e.add_asset(WRAP_NEAR);
+    e.owner_call(
+        "add_asset_volatility",
+        json!({
+            "asset_id": WRAP_NEAR,
+            "period_sec": 600,
+        }),
+        e.storage_deposit,
+    )
+    .assert_success();
+
+    let get_volatility = || {
+        e.get_price_data(Some(vec![format!("{}%600", WRAP_NEAR)]))
+            .prices[0]
+            .price
+    };
+
+    e.make_reports(&[100000]);
+    // Not available during the first window.
+    assert!(get_volatility().is_none());
+
+    e.skip_time(600);
+    e.make_reports(&[110000]);
+    let volatility = get_volatility().unwrap();
+    assert_eq!(volatility.decimals, 12);
+    // ln(1.1)
+    assert!((volatility.multiplier as i128 - 95310179804).abs() < 1000);
+
+    // A malformed period has no price.
+    let price_data = e.get_price_data(Some(vec![
+        format!("{}%10m", WRAP_NEAR),
+        format!("{}%600", WRAP_NEAR),
+    ]));
+    assert!(price_data.prices[0].price.is_none());
+    assert!(price_data.prices[1].price.is_some());
+
+    // Stale without recent reports.
+    e.skip_time(100);
+    assert!(get_volatility().is_none());
+}
+
+#[test]
+pub fn test_derived_emas() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+    e.add_asset(WRAP_NEAR);
+    e.add_asset(DAI);
+    let add_ema = |source: near_sdk::serde_json::Value| {
+        e.owner_call(
+            "add_asset_ema",
+            json!({
+                "asset_id": WRAP_NEAR,
+                "period_sec": 600,
+                "source": source,
+            }),
+            e.storage_deposit,
+        )
+    };
+    // The source EMA should exist.
+    assert!(!add_ema(json!({"Ema": {"period_sec": 600}})).is_ok());
+    e.add_asset_ema(WRAP_NEAR, 600);
+    add_ema(json!({"Ema": {"period_sec": 600}})).assert_success();
+    add_ema(json!({"Spread": {"period_sec": 600}})).assert_success();
+    add_ema(json!({"Pair": {"quote_asset_id": DAI}})).assert_success();
+    assert!(!add_ema(json!({"Pair": {"quote_asset_id": DAI}})).is_ok());
+
+    let report = |wrap_near: u128| {
+        e.report_prices(
+            &e.users[0],
+            vec![
+                AssetPrice {
+                    asset_id: DAI.to_string(),
+                    price: Price {
+                        multiplier: 10000,
+                        decimals: 28,
+                    },
+                },
+                AssetPrice {
+                    asset_id: WRAP_NEAR.to_string(),
+                    price: Price {
+                        multiplier: wrap_near,
+                        decimals: 28,
+                    },
+                },
+            ],
+        )
+        .assert_success();
+    };
+    let keys = [
+        "600".to_string(),
+        "600:ema:600".to_string(),
+        "600:spread:600".to_string(),
+        format!("600:pair:{}", DAI),
+    ];
+    let get_emas = || {
+        e.get_price_data(Some(
+            keys.iter()
+                .map(|key| format!("{}#{}", WRAP_NEAR, key))
+                .collect(),
+        ))
+        .prices
+        .into_iter()
+        .map(|price| {
+            let price = price.price.unwrap();
+            price.multiplier as f64 / 10f64.powi(price.decimals as i32)
+        })
+        .collect::<Vec<_>>()
+    };
+
+    report(100000);
+    let emas = get_emas();
+    assert!((emas[0] - 1e-23).abs() < 1e-30);
+    assert!((emas[1] - 1e-23).abs() < 1e-30);
+    assert!((emas[2] - 1.0).abs() < 1e-9);
+    assert!((emas[3] - 10.0).abs() < 1e-9);
+
+    e.skip_time(600);
+    report(200000);
+    let emas = get_emas();
+    let alpha = 1.0 - (-2.0f64).exp();
+    let ema = 1e-23 * (1.0 + alpha);
+    assert!((emas[0] / ema - 1.0).abs() < 1e-6);
+    assert!((emas[1] / (1e-23 * (1.0 + alpha * alpha)) - 1.0).abs() < 1e-6);
+    assert!((emas[2] - (1.0 + alpha * (2e-23 / ema - 1.0))).abs() < 1e-6);
+    assert!((emas[3] - 10.0 * (1.0 + alpha)).abs() < 1e-6);
+
+    // Quoted in DAI, the EMAs of prices are converted, while spreads and pairs are ratios.
+    let price_data: PriceData = e
+        .near
+        .view(
+            e.contract.account_id(),
+            "get_price_data",
+            &json!({
+                "asset_ids": keys
+                    .iter()
+                    .map(|key| format!("{}#{}", WRAP_NEAR, key))
+                    .collect::<Vec<_>>(),
+                "quote_asset_id": DAI,
+            })
+            .to_string()
+            .into_bytes(),
+        )
+        .unwrap_json();
+    let emas_in_dai = price_data
+        .prices
+        .into_iter()
+        .map(|price| {
+            let price = price.price.unwrap();
+            price.multiplier as f64 / 10f64.powi(price.decimals as i32)
+        })
+        .collect::<Vec<_>>();
+    assert!((emas_in_dai[0] / (emas[0] / 1e-24) - 1.0).abs() < 1e-6);
+    assert!((emas_in_dai[1] / (emas[1] / 1e-24) - 1.0).abs() < 1e-6);
+    assert!((emas_in_dai[2] - emas[2]).abs() < 1e-9);
+    assert!((emas_in_dai[3] - emas[3]).abs() < 1e-9);
+
+    // The median EMA can't be removed while derived EMAs use it.
+    assert!(!e
+        .owner_call(
+            "remove_asset_ema",
+            json!({
+                "asset_id": WRAP_NEAR,
+                "period_sec": 600,
+            }),
+            1,
+        )
+        .is_ok());
+    e.owner_call(
+        "remove_asset_ema",
+        json!({
+            "asset_id": WRAP_NEAR,
+            "period_sec": 600,
+            "source": {"Pair": {"quote_asset_id": DAI}},
+        }),
+        1,
+    )
+    .assert_success();
+}
+
+#[test]
+pub fn test_pair_ema_quote() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+    e.add_asset(WRAP_NEAR);
+    e.add_asset(DAI);
+    e.owner_call(
+        "add_asset_alias",
+        json!({
+            "alias_id": "usd.near",
+            "asset_id": DAI,
+        }),
+        e.storage_deposit,
+    )
+    .assert_success();
+    // The quote alias is resolved to the asset ID.
+    e.owner_call(
+        "add_asset_ema",
+        json!({
+            "asset_id": WRAP_NEAR,
+            "period_sec": 600,
+            "source": {"Pair": {"quote_asset_id": "usd.near"}},
+        }),
+        e.storage_deposit,
+    )
+    .assert_success();
+
+    let report = |dai_id: &str| {
+        e.report_prices(
+            &e.users[0],
+            vec![
+                AssetPrice {
+                    asset_id: dai_id.to_string(),
+                    price: Price {
+                        multiplier: 10000,
+                        decimals: 28,
+                    },
+                },
+                AssetPrice {
+                    asset_id: WRAP_NEAR.to_string(),
+                    price: Price {
+                        multiplier: 100000,
+                        decimals: 28,
+                    },
+                },
+            ],
+        )
+        .assert_success();
+    };
+    let get_pair = |dai_id: &str| {
+        e.get_price_data(Some(vec![format!("{}#600:pair:{}", WRAP_NEAR, dai_id)]))
+            .prices[0]
+            .price
+    };
+
+    report(DAI);
+    let pair = get_pair(DAI).unwrap();
+    assert!((pair.multiplier as f64 / 10f64.powi(pair.decimals as i32) - 10.0).abs() < 1e-9);
+
+    // Renaming the quote asset retargets the pair EMA.
+    e.owner_call(
+        "rename_asset",
+        json!({
+            "asset_id": DAI,
+            "new_asset_id": "dai.v2.near",
+        }),
+        e.storage_deposit,
+    )
+    .assert_success();
+    e.skip_time(60);
+    report("dai.v2.near");
+    assert!(get_pair(DAI).is_none());
+    assert!(get_pair("dai.v2.near").is_some());
+
+    // The quote asset can't be removed while the pair EMA uses it.
+    let proposal_id: u64 = e
+        .owner_call(
+            "propose_action",
+            json!({
+                "action": {
+                    "RemoveAsset": {
+                        "asset_id": "dai.v2.near",
+                    }
+                },
+            }),
+            e.storage_deposit,
+        )
+        .unwrap_json();
+    e.skip_time(24 * 60 * 60);
+    let execute = || {
+        e.owner_call(
+            "execute_proposal",
+            json!({
+                "proposal_id": proposal_id,
+            }),
+            1,
+        )
+    };
+    assert!(!execute().is_ok());
+    e.owner_call(
+        "remove_asset_ema",
+        json!({
+            "asset_id": WRAP_NEAR,
+            "period_sec": 600,
+            "source": {"Pair": {"quote_asset_id": "usd.near"}},
+        }),
+        1,
+    )
+    .assert_success();
+    execute().assert_success();
+}
+
+#[test]
+pub fn test_ema_warm_up() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+    e.add_asset(WRAP_NEAR);
+    e.add_asset_ema(WRAP_NEAR, 600);
+    e.owner_call(
+        "set_asset_ema_warm_up",
+        json!({
+            "asset_id": WRAP_NEAR,
+            "ema_warm_up_bps": 5000,
+        }),
+        e.storage_deposit,
+    )
+    .assert_success();
+
+    let get_ema = || {
+        e.get_price_data(Some(vec![format!("{}#600", WRAP_NEAR)]))
+            .prices[0]
+            .price
+    };
+
+    e.make_reports(&[100000]);
+    assert!(get_ema().is_none());
+    e.skip_time(200);
+    e.make_reports(&[110000]);
+    assert!(get_ema().is_none());
+
+    // Half of the period has passed.
+    e.skip_time(100);
+    e.make_reports(&[110000]);
+    assert!(get_ema().is_some());
+
+    let asset: Asset = e
+        .near
+        .view(
+            e.contract.account_id(),
+            "get_asset",
+            &json!({
+                "asset_id": WRAP_NEAR,
+            })
+            .to_string()
+            .into_bytes(),
+        )
+        .unwrap_json();
+    assert_eq!(asset.emas[0].num_samples, 2);
+}
+
+#[test]
+pub fn test_asset_alias() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+
+    e.add_asset(WRAP_NEAR);
+    e.add_asset_ema(WRAP_NEAR, 3600);
+
+    e.make_reports(&[100000]);
+
+    let res = e.owner_call(
+        "add_asset_alias",
+        json!({
+            "alias_id": "near",
+            "asset_id": WRAP_NEAR,
+        }),
+        e.storage_deposit,
+    );
+    res.assert_success();
+    assert!(res.logs()[0].starts_with("EVENT_JSON:"));
+    assert!(res.logs()[0].contains("\"event\":\"add_asset_alias\""));
+
+    // An alias can't shadow an existing asset.
+    e.add_asset(DAI);
+    assert!(!e
+        .owner_call(
+            "add_asset_alias",
+            json!({
+                "alias_id": DAI,
+                "asset_id": WRAP_NEAR,
+            }),
+            e.storage_deposit,
+        )
+        .is_ok());
+
+    let price_data = e.get_price_data(Some(vec!["near".to_string(), "near#3600".to_string()]));
+    assert_eq!(&price_data.prices[0].asset_id, "near");
+    assert_eq!(
+        &price_data.prices[0].price,
+        &Some(Price {
+            multiplier: 100000,
+            decimals: 28
+        })
+    );
+    assert_eq!(&price_data.prices[1].asset_id, "near#3600");
+    assert_eq!(
+        &price_data.prices[1].price,
+        &Some(Price {
+            multiplier: 100000,
+            decimals: 28
+        })
+    );
+
+    // Reports through the alias go to the aliased asset.
+    e.report_prices(
+        &e.users[0],
+        vec![AssetPrice {
+            asset_id: "near".to_string(),
+            price: Price {
+                multiplier: 110000,
+                decimals: 28,
+            },
+        }],
+    )
+    .assert_success();
+    let price_data = e.get_price_data(Some(vec![WRAP_NEAR.to_string()]));
+    assert_eq!(
+        &price_data.prices[0].price,
+        &Some(Price {
+            multiplier: 110000,
+            decimals: 28
+        })
+    );
+
+    let res = e.owner_call(
+        "remove_asset_alias",
+        json!({
+            "alias_id": "near",
+        }),
+        1,
+    );
+    res.assert_success();
+    assert!(res.logs()[0].contains("\"event\":\"remove_asset_alias\""));
+
+    let price_data = e.get_price_data(Some(vec!["near".to_string()]));
+    assert!(price_data.prices[0].price.is_none());
+}
+
+#[test]
+pub fn test_rename_asset() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+
+    e.add_asset(WRAP_NEAR);
+    e.add_asset_ema(WRAP_NEAR, 3600);
+
+    e.make_reports(&[100000]);
+
+    e.owner_call(
+        "add_asset_alias",
+        json!({
+            "alias_id": "near",
+            "asset_id": WRAP_NEAR,
+        }),
+        e.storage_deposit,
+    )
+    .assert_success();
+
+    let res = e.owner_call(
+        "rename_asset",
+        json!({
+            "asset_id": WRAP_NEAR,
+            "new_asset_id": "wrap.v2.near",
+        }),
+        e.storage_deposit,
+    );
+    res.assert_success();
+    assert!(res.logs()[0].contains("\"event\":\"rename_asset\""));
+
+    let price_data = e.get_price_data(Some(vec![
+        WRAP_NEAR.to_string(),
+        "wrap.v2.near".to_string(),
+        "wrap.v2.near#3600".to_string(),
+        "near".to_string(),
+    ]));
+    // The old asset ID is gone.
+    assert!(price_data.prices[0].price.is_none());
+    // Reports and EMAs moved to the new asset ID.
+    for asset_price in &price_data.prices[1..] {
+        assert_eq!(
+            &asset_price.price,
+            &Some(Price {
+                multiplier: 100000,
+                decimals: 28
+            })
+        );
+    }
+
+    let aliases: Vec<(AssetId, AssetId)> = e
+        .near
+        .view(
+            e.contract.account_id(),
+            "get_asset_aliases",
+            &json!({}).to_string().into_bytes(),
+        )
+        .unwrap_json();
+    assert_eq!(
+        aliases,
+        vec![("near".to_string(), "wrap.v2.near".to_string())]
+    );
+}
+
+#[test]
+pub fn test_storage_deposit() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+    e.add_oracle(&e.users[1]);
+
+    // 1 yocto doesn't cover the storage of the asset and its future reports.
+    assert!(!e
+        .owner_call(
+            "add_asset",
+            json!({
+                "asset_id": WRAP_NEAR,
+            }),
+            1,
+        )
+        .is_ok());
+
+    let storage_balance: StorageBalance = e
+        .near
+        .view(e.contract.account_id(), "get_storage_balance", &[])
+        .unwrap_json();
+    assert_eq!(storage_balance.assets.0, 0);
+    assert!(storage_balance.oracles.0 > 0);
+
+    let initial_balance = e.owner.account().unwrap().amount;
+    e.owner_call(
+        "add_asset",
+        json!({
+            "asset_id": WRAP_NEAR,
+        }),
+        to_yocto("10"),
+    )
+    .assert_success();
+    let balance = e.owner.account().unwrap().amount;
+    // The excess deposit is refunded.
+    assert!(initial_balance - balance < to_yocto("0.1"));
+
+    e.make_reports(&[100000, 110000]);
+    // Reports are covered by the deposit reserved by `add_asset`.
+    let new_storage_balance: StorageBalance = e
+        .near
+        .view(e.contract.account_id(), "get_storage_balance", &[])
+        .unwrap_json();
+    assert!(new_storage_balance.assets.0 > 0);
+    assert!(new_storage_balance.reports.0 > 0);
+    assert!(
+        new_storage_balance.storage_cost.0 - storage_balance.storage_cost.0
+            <= initial_balance - balance
+    );
+}
+
+#[test]
+pub fn test_timelock() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+    e.add_asset(WRAP_NEAR);
+
+    let timelock_duration_sec: DurationSec = e
+        .near
+        .view(e.contract.account_id(), "get_timelock_duration_sec", &[])
+        .unwrap_json();
+    assert_eq!(timelock_duration_sec, 24 * 60 * 60);
+
+    let proposal_id: u64 = e
+        .owner_call(
+            "propose_action",
+            json!({
+                "action": {
+                    "SetRecencyDurationSec": {
+                        "recency_duration_sec": 60,
+                    }
+                },
+            }),
+            e.storage_deposit,
+        )
+        .unwrap_json();
+
+    let proposals: Vec<(u64, Proposal)> = e
+        .near
+        .view(
+            e.contract.account_id(),
+            "get_proposals",
+            &json!({}).to_string().into_bytes(),
+        )
+        .unwrap_json();
+    assert_eq!(proposals.len(), 1);
+    assert_eq!(proposals[0].0, proposal_id);
+    assert_eq!(proposals[0].1.executable_at, ts(timelock_duration_sec));
+
+    // Can't be executed before the delay.
+    let execute = || {
+        e.owner_call(
+            "execute_proposal",
+            json!({
+                "proposal_id": proposal_id,
+            }),
+            1,
+        )
+    };
+    assert!(!execute().is_ok());
+    assert_eq!(e.get_price_data(None).recency_duration_sec, 90);
+
+    e.skip_time(timelock_duration_sec);
+    let res = execute();
+    res.assert_success();
+    assert!(res.logs()[0].contains("\"event\":\"execute_proposal\""));
+    assert_eq!(e.get_price_data(None).recency_duration_sec, 60);
+
+    // Executed proposals are removed.
+    assert!(!execute().is_ok());
+
+    // Cancelled proposals can't be executed.
+    let proposal_id: u64 = e
+        .owner_call(
+            "propose_action",
+            json!({
+                "action": {
+                    "RemoveAsset": {
+                        "asset_id": WRAP_NEAR,
+                    }
+                },
+            }),
+            e.storage_deposit,
+        )
+        .unwrap_json();
+    e.owner_call(
+        "cancel_proposal",
+        json!({
+            "proposal_id": proposal_id,
+        }),
+        1,
+    )
+    .assert_success();
+    e.skip_time(timelock_duration_sec);
+    assert!(!e
+        .owner_call(
+            "execute_proposal",
+            json!({
+                "proposal_id": proposal_id,
+            }),
+            1,
+        )
+        .is_ok());
+    assert_eq!(e.get_price_data(None).prices.len(), 1);
+}
+
+#[test]
+pub fn test_remove_asset() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+    e.add_asset(WRAP_NEAR);
+    e.make_reports(&[100000]);
+    e.owner_call(
+        "add_asset_alias",
+        json!({
+            "alias_id": "near",
+            "asset_id": WRAP_NEAR,
+        }),
+        e.storage_deposit,
+    )
+    .assert_success();
+    // An alias can't be added as an asset.
+    assert!(!e
+        .owner_call(
+            "add_asset",
+            json!({
+                "asset_id": "near",
+            }),
+            e.storage_deposit,
+        )
+        .is_ok());
+
+    let proposal_id: u64 = e
+        .owner_call(
+            "propose_action",
+            json!({
+                "action": {
+                    "RemoveAsset": {
+                        "asset_id": WRAP_NEAR,
+                    }
+                },
+            }),
+            e.storage_deposit,
+        )
+        .unwrap_json();
+    e.skip_time(24 * 60 * 60);
+    let res = e.owner_call(
+        "execute_proposal",
+        json!({
+            "proposal_id": proposal_id,
+        }),
+        1,
+    );
+    res.assert_success();
+    assert!(res
+        .logs()
+        .iter()
+        .any(|log| log.contains("\"event\":\"remove_asset_alias\"")));
+
+    let aliases: Vec<(AssetId, AssetId)> = e
+        .near
+        .view(
+            e.contract.account_id(),
+            "get_asset_aliases",
+            &json!({}).to_string().into_bytes(),
+        )
+        .unwrap_json();
+    assert!(aliases.is_empty());
+
+    // The asset starts without the old reports when it's added again.
+    e.add_asset(WRAP_NEAR);
+    let reports: Vec<Report> = e
+        .near
+        .view(
+            e.contract.account_id(),
+            "get_asset_reports",
+            &json!({
+                "asset_id": WRAP_NEAR,
+            })
+            .to_string()
+            .into_bytes(),
+        )
+        .unwrap_json();
+    assert!(reports.is_empty());
+}
+
+#[test]
+pub fn test_pause() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+    e.add_asset(WRAP_NEAR);
+
+    // Pause takes effect immediately.
+    e.owner_call("pause", json!({}), 1).assert_success();
+    let is_paused: bool = e
+        .near
+        .view(e.contract.account_id(), "is_paused", &[])
+        .unwrap_json();
+    assert!(is_paused);
+
+    assert!(!e
+        .report_prices(
+            &e.users[0],
+            vec![AssetPrice {
+                asset_id: WRAP_NEAR.to_string(),
+                price: Price {
+                    multiplier: 100000,
+                    decimals: 28,
+                },
+            }],
+        )
+        .is_ok());
+
+    // Unpause goes through the timelock.
+    let proposal_id: u64 = e
+        .owner_call(
+            "propose_action",
+            json!({
+                "action": "Unpause",
+            }),
+            e.storage_deposit,
+        )
+        .unwrap_json();
+    e.skip_time(24 * 60 * 60);
+    e.owner_call(
+        "execute_proposal",
+        json!({
+            "proposal_id": proposal_id,
+        }),
+        1,
+    )
+    .assert_success();
+
+    e.make_reports(&[100000]);
+}
+
+#[test]
+pub fn test_transfer_ownership() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+    let new_owner = &e.users[0];
+
+    let accept_ownership = |user: &UserAccount| {
+        user.call(
+            e.contract.account_id(),
+            "accept_ownership",
+            &json!({}).to_string().into_bytes(),
+            DEFAULT_GAS.0,
+            1,
+        )
+    };
+    let get_owner_id = || -> AccountId {
+        e.near
+            .view(e.contract.account_id(), "get_owner_id", &[])
+            .unwrap_json()
+    };
+
+    e.owner_call(
+        "propose_owner",
+        json!({
+            "account_id": new_owner.account_id(),
+        }),
+        1,
+    )
+    .assert_success();
+
+    let pending_owner: Option<PendingOwner> = e
+        .near
+        .view(e.contract.account_id(), "get_pending_owner", &[])
+        .unwrap_json();
+    assert_eq!(
+        pending_owner.map(|p| p.account_id),
+        Some(new_owner.account_id())
+    );
+
+    // Can't be accepted before the timelock delay.
+    assert!(!accept_ownership(new_owner).is_ok());
+
+    e.skip_time(24 * 60 * 60);
+
+    // Only the pending owner can accept.
+    assert!(!accept_ownership(&e.users[1]).is_ok());
+    assert_eq!(get_owner_id(), a(OWNER_ID));
+
+    accept_ownership(new_owner).assert_success();
+    assert_eq!(get_owner_id(), new_owner.account_id());
+
+    // The previous owner has no access anymore.
+    assert!(!e
+        .owner_call(
+            "propose_owner",
+            json!({
+                "account_id": OWNER_ID,
+            }),
+            1,
+        )
+        .is_ok());
+
+    // The roles moved to the new owner.
+    let get_account_roles = |account_id: AccountId| -> Vec<Role> {
+        e.near
+            .view(
+                e.contract.account_id(),
+                "get_account_roles",
+                &json!({
+                    "account_id": account_id,
+                })
+                .to_string()
+                .into_bytes(),
+            )
+            .unwrap_json()
+    };
+    assert!(get_account_roles(a(OWNER_ID)).is_empty());
+    assert_eq!(
+        get_account_roles(new_owner.account_id()),
+        Role::ALL.to_vec()
+    );
+    assert!(!e
+        .owner_call(
+            "add_asset",
+            json!({
+                "asset_id": WRAP_NEAR,
+            }),
+            e.storage_deposit,
+        )
+        .is_ok());
+    assert!(!e
+        .owner_call(
+            "stage_upgrade",
+            json!({
+                "code": "",
+            }),
+            e.storage_deposit,
+        )
+        .is_ok());
+    new_owner
+        .call(
+            e.contract.account_id(),
+            "add_asset",
+            &json!({
+                "asset_id": WRAP_NEAR,
+            })
+            .to_string()
+            .into_bytes(),
+            DEFAULT_GAS.0,
+            e.storage_deposit,
+        )
+        .assert_success();
+
+    // The proposal expires.
+    new_owner
+        .call(
+            e.contract.account_id(),
+            "propose_owner",
+            &json!({
+                "account_id": OWNER_ID,
+            })
+            .to_string()
+            .into_bytes(),
+            DEFAULT_GAS.0,
+            1,
+        )
+        .assert_success();
+    e.skip_time(8 * 24 * 60 * 60 + 1);
+    assert!(!accept_ownership(&e.owner).is_ok());
+    assert_eq!(get_owner_id(), new_owner.account_id());
+}
+
+#[test]
+pub fn test_roles() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+    let manager = &e.users[4];
+
+    let add_asset = |user: &UserAccount| {
+        user.call(
+            e.contract.account_id(),
+            "add_asset",
+            &json!({
+                "asset_id": WRAP_NEAR,
+            })
+            .to_string()
+            .into_bytes(),
+            DEFAULT_GAS.0,
+            e.storage_deposit,
+        )
+    };
+
+    // The owner starts with all roles.
+    let roles: Vec<Role> = e
+        .near
+        .view(
+            e.contract.account_id(),
+            "get_account_roles",
+            &json!({
+                "account_id": OWNER_ID,
+            })
+            .to_string()
+            .into_bytes(),
+        )
+        .unwrap_json();
+    assert_eq!(roles.len(), 4);
+
+    assert!(!add_asset(manager).is_ok());
+
+    e.owner_call(
+        "grant_role",
+        json!({
+            "account_id": manager.account_id(),
+            "role": "AssetManager",
+        }),
+        e.storage_deposit,
+    )
+    .assert_success();
+
+    add_asset(manager).assert_success();
+
+    // The role doesn't give access to other actions.
+    assert!(!manager
+        .call(
+            e.contract.account_id(),
+            "add_oracle",
+            &json!({
+                "account_id": manager.account_id(),
+            })
+            .to_string()
+            .into_bytes(),
+            DEFAULT_GAS.0,
+            e.storage_deposit,
+        )
+        .is_ok());
+    // Only the owner manages roles.
+    assert!(!manager
+        .call(
+            e.contract.account_id(),
+            "grant_role",
+            &json!({
+                "account_id": manager.account_id(),
+                "role": "OracleManager",
+            })
+            .to_string()
+            .into_bytes(),
+            DEFAULT_GAS.0,
+            e.storage_deposit,
+        )
+        .is_ok());
+    // Only an upgrader can upgrade.
+    assert!(!manager
+        .create_transaction(a(ORACLE_ID))
+        .function_call(
+            "upgrade".to_string(),
+            CONTARCT_WASM_BYTES.to_vec(),
+            MAX_GAS.0,
+            0,
+        )
+        .submit()
+        .is_ok());
+
+    e.owner_call(
+        "revoke_role",
+        json!({
+            "account_id": manager.account_id(),
+            "role": "AssetManager",
+        }),
+        1,
+    )
+    .assert_success();
+
+    assert!(!manager
+        .call(
+            e.contract.account_id(),
+            "add_asset",
+            &json!({
+                "asset_id": DAI,
+            })
+            .to_string()
+            .into_bytes(),
+            DEFAULT_GAS.0,
+            e.storage_deposit,
+        )
+        .is_ok());
+}
+
+#[test]
+pub fn test_update() {
+    let mut e = Env::setup(&CONTRACT_0_6_0_WASM_BYTES);
+    e.storage_deposit = 1;
+
+    e.add_oracle(&e.users[0]);
+    e.add_oracle(&e.users[1]);
+    e.add_oracle(&e.users[2]);
+
+    e.add_asset(WRAP_NEAR);
+
+    e.make_reports(&[100000, 110000, 106000]);
+
+    let price_data = e.get_price_data(None);
+    assert_eq!(price_data.prices.len(), 1);
+
+    let version: String = e
+        .near
+        .view(e.contract.account_id(), "get_version", &[])
+        .unwrap_json();
+
+    assert_eq!(version, PREVIOUS_VERSION);
+
+    e.owner
+        .create_transaction(a(ORACLE_ID))
+        .function_call(
+            "upgrade".to_string(),
+            CONTARCT_WASM_BYTES.to_vec(),
+            MAX_GAS.0,
+            0,
+        )
+        .submit();
+
+    let price_data = e.get_price_data(None);
+    assert_eq!(price_data.prices.len(), 1);
+    assert_eq!(
+        &price_data.prices[0].price,
+        &Some(Price {
+            multiplier: 106000,
+            decimals: 28
+        })
+    );
+
+    let version: String = e
+        .near
+        .view(e.contract.account_id(), "get_version", &[])
+        .unwrap_json();
+
+    assert_eq!(version, LATEST_VERSION);
+
+    // Assets and oracles are migrated in batches. Reports can't be changed until it's done.
+    let status = e.get_migration_status();
+    assert_eq!(status.schema_version, 1);
+    assert!(status.in_progress);
+    assert_eq!(status.migrated_assets, 0);
+    assert_eq!(status.num_assets, 1);
+    assert_eq!(status.num_oracles, 3);
+    assert!(!e
+        .report_prices(
+            &e.users[0],
+            vec![AssetPrice {
+                asset_id: WRAP_NEAR.to_string(),
+                price: Price {
+                    multiplier: 120000,
+                    decimals: 28,
+                },
+            }],
+        )
+        .is_ok());
+
+    // Views still work and read the reports of assets that are not migrated yet.
+    let get_asset_reports = || -> Vec<Report> {
+        e.near
+            .view(
+                e.contract.account_id(),
+                "get_asset_reports",
+                &json!({
+                    "asset_id": WRAP_NEAR,
+                })
+                .to_string()
+                .into_bytes(),
+            )
+            .unwrap_json()
+    };
+    assert_eq!(get_asset_reports().len(), 3);
+    let _: StorageBalance = e
+        .near
+        .view(e.contract.account_id(), "get_storage_balance", &[])
+        .unwrap_json();
+
+    let migrate_batch = || -> MigrationStatus {
+        e.users[0]
+            .call(
+                e.contract.account_id(),
+                "migrate_batch",
+                &json!({
+                    "limit": 2,
+                })
+                .to_string()
+                .into_bytes(),
+                MAX_GAS.0,
+                0,
+            )
+            .unwrap_json()
+    };
+    let status = migrate_batch();
+    assert!(status.in_progress);
+    assert_eq!(status.migrated_assets, 1);
+    assert_eq!(status.migrated_oracles, 1);
+    let status = migrate_batch();
+    assert!(!status.in_progress);
+    assert_eq!(status.migrated_oracles, 3);
+    // Nothing left to migrate.
+    assert!(!e.users[0]
+        .call(
+            e.contract.account_id(),
+            "migrate_batch",
+            &json!({}).to_string().into_bytes(),
+            MAX_GAS.0,
+            0,
+        )
+        .is_ok());
+
+    // Reports were moved out of the asset by the migration.
+    assert_eq!(get_asset_reports().len(), 3);
+
+    // New reports replace the migrated ones.
+    e.make_reports(&[120000, 120000]);
+    let price_data = e.get_price_data(None);
+    assert_eq!(
+        &price_data.prices[0].price,
+        &Some(Price {
+            multiplier: 120000,
+            decimals: 28
+        })
+    );
+    let asset: Asset = e
+        .near
+        .view(
+            e.contract.account_id(),
+            "get_asset",
+            &json!({
+                "asset_id": WRAP_NEAR,
+            })
+            .to_string()
+            .into_bytes(),
+        )
+        .unwrap_json();
+    assert_eq!(asset.report_cache.len(), 3);
+}
+
+#[test]
+pub fn test_staged_upgrade() {
+    let e = Env::setup(&CONTARCT_WASM_BYTES);
+
+    e.add_oracle(&e.users[0]);
+    e.add_asset(WRAP_NEAR);
+    e.make_reports(&[100000]);
+
+    let stage_upgrade = |user: &UserAccount| {
+        user.create_transaction(a(ORACLE_ID))
+            .function_call(
+                "stage_upgrade".to_string(),
+                CONTARCT_WASM_BYTES.to_vec(),
+                MAX_GAS.0,
+                0,
+            )
+            .submit()
+    };
+    // Requires the `Upgrader` role.
+    assert!(!stage_upgrade(&e.users[0]).is_ok());
+    stage_upgrade(&e.owner).assert_success();
+
+    let staged_upgrade: Option<StagedUpgrade> = e
+        .near
+        .view(e.contract.account_id(), "get_staged_upgrade", &[])
+        .unwrap_json();
+    let staged_upgrade = staged_upgrade.unwrap();
+    assert_eq!(staged_upgrade.deployable_at, ts(24 * 60 * 60));
+
+    let deploy_upgrade = |code_hash: &str| {
+        e.owner.call(
+            e.contract.account_id(),
+            "deploy_upgrade",
+            &json!({
+                "code_hash": code_hash,
+                "version": LATEST_VERSION,
+            })
+            .to_string()
+            .into_bytes(),
+            MAX_GAS.0,
+            1,
+        )
+    };
+    let code_hash: String = String::from(&staged_upgrade.code_hash);
+
+    // Can't be deployed before the delay.
+    assert!(!deploy_upgrade(&code_hash).is_ok());
+
+    e.skip_time(24 * 60 * 60);
+    // The code hash has to match the staged code.
+    assert!(!deploy_upgrade("11111111111111111111111111111111").is_ok());
+    // Other accounts can't deploy.
+    assert!(!e.users[0]
+        .call(
+            e.contract.account_id(),
+            "deploy_upgrade",
+            &json!({
+                "code_hash": code_hash,
+                "version": LATEST_VERSION,
+            })
+            .to_string()
+            .into_bytes(),
+            MAX_GAS.0,
+            1,
+        )
+        .is_ok());
+
+    // The deployment is reverted if the new code is not of the expected version.
+    assert!(!e
+        .owner
+        .call(
+            e.contract.account_id(),
+            "deploy_upgrade",
+            &json!({
+                "code_hash": code_hash,
+                "version": "0.0.0",
+            })
+            .to_string()
+            .into_bytes(),
+            MAX_GAS.0,
+            1,
+        )
+        .is_ok());
+    // A failed deployment keeps the staged code and doesn't record the upgrade.
+    let staged: Option<StagedUpgrade> = e
+        .near
+        .view(e.contract.account_id(), "get_staged_upgrade", &[])
+        .unwrap_json();
+    assert_eq!(String::from(&staged.unwrap().code_hash), code_hash);
+    let current_code_hash: Option<Base58CryptoHash> = e
+        .near
+        .view(e.contract.account_id(), "get_code_hash", &[])
+        .unwrap_json();
+    assert!(current_code_hash.is_none());
+
+    deploy_upgrade(&code_hash).assert_success();
+    let current_code_hash: Option<Base58CryptoHash> = e
+        .near
+        .view(e.contract.account_id(), "get_code_hash", &[])
+        .unwrap_json();
+    assert_eq!(String::from(&current_code_hash.unwrap()), code_hash);
+
+    let version: String = e
+        .near
+        .view(e.contract.account_id(), "get_version", &[])
+        .unwrap_json();
+    assert_eq!(version, LATEST_VERSION);
+    let staged_upgrade: Option<StagedUpgrade> = e
+        .near
+        .view(e.contract.account_id(), "get_staged_upgrade", &[])
+        .unwrap_json();
+    assert!(staged_upgrade.is_none());
+    assert_eq!(e.get_price_data(None).prices.len(), 1);
+}
+
+#[test]
+pub fn test_rollback() {
+    let mut e = Env::setup(&CONTRACT_0_6_0_WASM_BYTES);
+    e.storage_deposit = 1;
+
+    e.add_oracle(&e.users[0]);
+    e.add_oracle(&e.users[1]);
+    e.add_asset(WRAP_NEAR);
+    e.make_reports(&[100000, 110000]);
+
+    let upgrade = |method_name: &str, user: &UserAccount, wasm: &[u8]| {
+        user.create_transaction(a(ORACLE_ID))
+            .function_call(method_name.to_string(), wasm.to_vec(), MAX_GAS.0, 0)
+            .submit()
+    };
+    let get_version = || -> String {
+        e.near
+            .view(e.contract.account_id(), "get_version", &[])
+            .unwrap_json()
+    };
+    let migrate_batch = || {
+        e.users[0]
+            .call(
+                e.contract.account_id(),
+                "migrate_batch",
+                &json!({}).to_string().into_bytes(),
+                MAX_GAS.0,
+                0,
+            )
+            .assert_success();
+    };
+
+    upgrade("upgrade", &e.owner, &CONTARCT_WASM_BYTES).assert_success();
+    assert_eq!(get_version(), LATEST_VERSION);
+    migrate_batch();
+
+    let previous_code: Option<PreviousCode> = e
+        .near
+        .view(e.contract.account_id(), "get_previous_code", &[])
+        .unwrap_json();
+    let previous_code = previous_code.unwrap();
+    assert_eq!(
+        String::from(&previous_code.code_hash),
+        "4sUttxKK4gJpWr1mhfNddBt497ZiXsRMbTYvCeQQbjR2"
+    );
+    assert_eq!(previous_code.schema_version, 0);
+
+    // A staged upgrade keeps the previous code, since the hash of the running code is unknown.
+    // The rollback to it is allowed, since the schema version is the same.
+    upgrade("stage_upgrade", &e.owner, &CONTARCT_WASM_BYTES).assert_success();
+    let staged_upgrade: Option<StagedUpgrade> = e
+        .near
+        .view(e.contract.account_id(), "get_staged_upgrade", &[])
+        .unwrap_json();
+    e.skip_time(24 * 60 * 60);
+    e.owner
+        .call(
+            e.contract.account_id(),
+            "deploy_upgrade",
+            &json!({
+                "code_hash": staged_upgrade.unwrap().code_hash,
+                "version": LATEST_VERSION,
+            })
+            .to_string()
+            .into_bytes(),
+            MAX_GAS.0,
+            1,
+        )
+        .assert_success();
+    let previous_code: Option<PreviousCode> = e
+        .near
+        .view(e.contract.account_id(), "get_previous_code", &[])
+        .unwrap_json();
+    assert_eq!(
+        String::from(&previous_code.unwrap().code_hash),
+        "4sUttxKK4gJpWr1mhfNddBt497ZiXsRMbTYvCeQQbjR2"
+    );
+
+    // Reports made by the new version are kept by the rollback.
+    e.skip_time(10);
+    e.report_prices(
+        &e.users[1],
+        vec![AssetPrice {
+            asset_id: WRAP_NEAR.to_string(),
+            price: Price {
+                multiplier: 120000,
+                decimals: 28,
+            },
+        }],
+    )
+    .assert_success();
+
+    // Only the `Upgrader` can roll back and only to the previous code.
+    assert!(!upgrade("rollback", &e.users[0], &CONTRACT_0_6_0_WASM_BYTES).is_ok());
+    assert!(!upgrade("rollback", &e.owner, &CONTARCT_WASM_BYTES).is_ok());
+    assert_eq!(get_version(), LATEST_VERSION);
+
+    upgrade("rollback", &e.owner, &CONTRACT_0_6_0_WASM_BYTES).assert_success();
+    assert_eq!(get_version(), PREVIOUS_VERSION);
+
+    let price_data = e.get_price_data(None);
+    assert_eq!(
+        &price_data.prices[0].price,
+        &Some(Price {
+            multiplier: 120000,
+            decimals: 28
+        })
+    );
+    e.make_reports(&[130000, 130000]);
+
+    // The rolled back state can be upgraded again.
+    upgrade("upgrade", &e.owner, &CONTARCT_WASM_BYTES).assert_success();
+    assert_eq!(get_version(), LATEST_VERSION);
+    migrate_batch();
+    let price_data = e.get_price_data(None);
+    assert_eq!(
+        &price_data.prices[0].price,
+        &Some(Price {
+            multiplier: 130000,
+            decimals: 28
+        })
+    );
+    e.make_reports(&[140000, 140000]);
+    let price_data = e.get_price_data(None);
+    assert_eq!(
+        &price_data.prices[0].price,
+        &Some(Price {
+            multiplier: 140000,
+            decimals: 28
+        })
+    );
+}
+
+/// Returns gas burnt by a `report_prices` call for all assets, after every oracle already
+/// reported all assets.
+fn report_prices_gas(
+    wasm_binary: &[u8],
+    storage_deposit: Balance,
+    num_oracles: usize,
+    num_assets: usize,
+) -> Gas {
+    let mut e = Env::setup(wasm_binary);
+    e.storage_deposit = storage_deposit;
+
+    let oracles: Vec<UserAccount> = (0..num_oracles)
+        .map(|i| {
+            e.near
+                .create_user(a(format!("oracle_{}.near", i).as_str()), to_yocto("100"))
+        })
+        .collect();
+    for oracle in &oracles {
+        e.add_oracle(oracle);
+    }
+
+    let asset_ids: Vec<AssetId> = (0..num_assets)
+        .map(|i| format!("asset_{}.near", i))
+        .collect();
+    for asset_id in &asset_ids {
+        e.add_asset(asset_id);
+        e.add_asset_ema(asset_id, 3600);
+    }
+
+    let prices = |multiplier: u128| -> Vec<AssetPrice> {
+        asset_ids
+            .iter()
+            .map(|asset_id| AssetPrice {
+                asset_id: asset_id.clone(),
+                price: Price {
+                    multiplier,
+                    decimals: 28,
+                },
+            })
+            .collect()
+    };
+
+    for oracle in &oracles {
+        e.report_prices(oracle, prices(100000)).assert_success();
+    }
+
+    e.skip_time(10);
+    let res = e.report_prices(&oracles[0], prices(110000));
+    res.assert_success();
+    res.gas_burnt()
+}
+
+#[test]
+fn test_report_prices_gas() {
+    let num_assets = 10;
+    let mut gas = vec![];
+    for num_oracles in [3, 10, 30] {
+        let before = report_prices_gas(&CONTRACT_0_6_0_WASM_BYTES, 1, num_oracles, num_assets);
+        let after = report_prices_gas(&CONTARCT_WASM_BYTES, to_yocto("1"), num_oracles, num_assets);
+        println!(
+            "report_prices with {} oracles and {} assets: {} -> {} TGas",
+            num_oracles,
+            num_assets,
+            before.0 as f64 / 1e12,
+            after.0 as f64 / 1e12
+        );
+        gas.push((before, after));
+    }
+    let (first_before, first_after) = gas[0];
+    let (last_before, last_after) = gas[gas.len() - 1];
+    // Gas per additional oracle grows slower with reports stored outside of the asset.
+    assert!(last_after.0 - first_after.0 < last_before.0 - first_before.0);
+}
+
+#[test]
+fn test_confirm_prices_gas() {
+    let mut e = Env::setup(&CONTARCT_WASM_BYTES);
+    e.storage_deposit = to_yocto("1");
+
+    let oracles: Vec<UserAccount> = (0..10)
+        .map(|i| {
+            e.near
+                .create_user(a(format!("oracle_{}.near", i).as_str()), to_yocto("100"))
+        })
+        .collect();
+    for oracle in &oracles {
+        e.add_oracle(oracle);
+    }
+
+    let asset_ids: Vec<AssetId> = (0..10).map(|i| format!("asset_{}.near", i)).collect();
+    for asset_id in &asset_ids {
+        e.add_asset(asset_id);
+        e.add_asset_ema(asset_id, 3600);
+    }
+
+    let prices = || -> Vec<AssetPrice> {
+        asset_ids
+            .iter()
+            .map(|asset_id| AssetPrice {
+                asset_id: asset_id.clone(),
+                price: Price {
+                    multiplier: 100000,
+                    decimals: 28,
+                },
+            })
+            .collect()
+    };
+    for oracle in &oracles {
+        e.report_prices(oracle, prices()).assert_success();
+    }
+
+    e.skip_time(10);
+    let report = e.report_prices(&oracles[0], prices());
+    report.assert_success();
+    let confirm = e.confirm_prices(&oracles[1], asset_ids);
+    confirm.assert_success();
+    println!(
+        "Refreshing 10 assets with 10 oracles: report_prices {} TGas, confirm_prices {} TGas",
+        report.gas_burnt().0 as f64 / 1e12,
+        confirm.gas_burnt().0 as f64 / 1e12
+    );
+    assert!(confirm.gas_burnt().0 < report.gas_burnt().0);
 }
 
 #[test]